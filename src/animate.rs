@@ -0,0 +1,268 @@
+//! Terminal playback for simulations that advance one step at a time. A
+//! simulation hands over a `Frame` per step through a callback, and the
+//! `Player` draws it with raw ANSI escape codes while taking play, pause,
+//! step and speed commands from the keyboard.
+
+use std::{
+    io::{self, Read, Write},
+    process::{Command as Shell, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+pub type Rgb = (u8, u8, u8);
+
+/// One character cell of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub glyph: char,
+    pub foreground: Rgb,
+    pub background: Rgb,
+}
+
+impl Cell {
+    /// A grey cell, brighter the closer `level` is to `max`
+    pub fn shade(glyph: char, level: u32, max: u32) -> Self {
+        let grey = (level.min(max) * 200 / max.max(1)) as u8 + 20;
+        Cell {
+            glyph,
+            foreground: (255, 255, 255),
+            background: (grey, grey, grey),
+        }
+    }
+
+    /// A cell that stands out from any shade of grey
+    pub fn highlight(glyph: char) -> Self {
+        Cell {
+            glyph,
+            foreground: (0, 0, 0),
+            background: (255, 200, 0),
+        }
+    }
+}
+
+/// Everything drawn for a single step: a grid of cells with a line of text
+/// underneath
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub cells: Vec<Vec<Cell>>,
+    pub caption: String,
+}
+
+impl Frame {
+    pub fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        // home the cursor and draw over the last frame rather than clearing,
+        // which flickers
+        write!(out, "\x1b[H")?;
+        for row in &self.cells {
+            for cell in row {
+                let ((fr, fg, fb), (br, bg, bb)) = (cell.foreground, cell.background);
+                write!(
+                    out,
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m{}",
+                    fr, fg, fb, br, bg, bb, cell.glyph
+                )?;
+            }
+            writeln!(out, "\x1b[0m\x1b[K")?;
+        }
+        writeln!(out, "{}\x1b[K", self.caption)?;
+        out.flush()
+    }
+}
+
+/// What the person watching can ask for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    TogglePause,
+    Step,
+    Faster,
+    Slower,
+    Quit,
+}
+
+impl Command {
+    /// space pauses and resumes, `n` steps while paused, `+` and `-` change
+    /// the speed and `q` quits
+    pub fn from_key(key: u8) -> Option<Command> {
+        match key {
+            b' ' => Some(Command::TogglePause),
+            b'n' | b'.' => Some(Command::Step),
+            b'+' | b'=' => Some(Command::Faster),
+            b'-' | b'_' => Some(Command::Slower),
+            b'q' | 0x03 => Some(Command::Quit),
+            _ => None,
+        }
+    }
+}
+
+const FASTEST: Duration = Duration::from_millis(10);
+const SLOWEST: Duration = Duration::from_secs(2);
+
+/// Plays frames from `next_frame`, which is called with the step number
+/// (starting at 0) and returns `None` once the simulation is over.
+pub struct Player<F: FnMut(usize) -> Option<Frame>> {
+    next_frame: F,
+    delay: Duration,
+    paused: bool,
+    step: usize,
+}
+
+impl<F: FnMut(usize) -> Option<Frame>> Player<F> {
+    pub fn new(next_frame: F, delay: Duration) -> Self {
+        Player {
+            next_frame,
+            delay: delay.clamp(FASTEST, SLOWEST),
+            paused: false,
+            step: 0,
+        }
+    }
+
+    pub fn paused(mut self) -> Self {
+        self.paused = true;
+        self
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Draws one frame, returning false if the simulation has finished
+    fn advance(&mut self, out: &mut impl Write) -> io::Result<bool> {
+        match (self.next_frame)(self.step) {
+            Some(frame) => {
+                frame.draw(out)?;
+                self.step += 1;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Runs until the simulation ends, `Quit` arrives or the commands dry
+    /// up, returning how many frames were drawn. While playing, the player
+    /// waits up to the current delay for a command before drawing the next
+    /// frame; while paused it only moves when told to.
+    pub fn run(&mut self, commands: &Receiver<Command>, out: &mut impl Write) -> io::Result<usize> {
+        write!(out, "\x1b[?25l\x1b[2J")?;
+        let result = self.play(commands, out);
+        write!(out, "\x1b[0m\x1b[?25h")?;
+        out.flush()?;
+        result.map(|_| self.step)
+    }
+
+    fn play(&mut self, commands: &Receiver<Command>, out: &mut impl Write) -> io::Result<()> {
+        if !self.advance(out)? {
+            return Ok(());
+        }
+        loop {
+            let command = if self.paused {
+                commands.recv().ok()
+            } else {
+                match commands.recv_timeout(self.delay) {
+                    Ok(command) => Some(command),
+                    Err(RecvTimeoutError::Timeout) => {
+                        if !self.advance(out)? {
+                            return Ok(());
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => None,
+                }
+            };
+            match command {
+                Some(Command::TogglePause) => self.paused = !self.paused,
+                Some(Command::Step) => {
+                    self.paused = true;
+                    if !self.advance(out)? {
+                        return Ok(());
+                    }
+                }
+                Some(Command::Faster) => self.delay = (self.delay / 2).max(FASTEST),
+                Some(Command::Slower) => self.delay = (self.delay * 2).min(SLOWEST),
+                Some(Command::Quit) | None => return Ok(()),
+            }
+        }
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Shell::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The terminal while a `play` is going on: reading keys one at a time
+/// without echoing them, with a thread turning them into commands. Dropping
+/// it, whether playback finished or something panicked, stops the thread
+/// and puts the terminal back the way it was.
+struct Session {
+    saved: String,
+    stop: Arc<AtomicBool>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Session {
+    fn start(commands: Sender<Command>) -> io::Result<Session> {
+        let saved = stty(&["-g"])?;
+        // reads give up after a tenth of a second without a key, so that the
+        // thread gets a chance to notice it should stop instead of sitting on
+        // stdin and swallowing whatever is typed after playback
+        stty(&["-icanon", "-echo", "min", "0", "time", "1"])?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let reader = thread::spawn(move || {
+            let mut key = [0];
+            while !stopped.load(Ordering::Relaxed) {
+                let command = match io::stdin().read(&mut key) {
+                    Ok(0) => continue,
+                    Ok(_) => match Command::from_key(key[0]) {
+                        Some(command) => command,
+                        None => continue,
+                    },
+                    Err(_) => break,
+                };
+                if commands.send(command).is_err() || command == Command::Quit {
+                    break;
+                }
+            }
+        });
+        Ok(Session {
+            saved,
+            stop,
+            reader: Some(reader),
+        })
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+        let mut out = io::stdout();
+        let _ = write!(out, "\x1b[0m\x1b[?25h");
+        let _ = out.flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Plays `next_frame` on the terminal, reading single keypresses from stdin.
+/// The terminal is switched out of line-buffered mode with `stty` for the
+/// duration, and put back afterwards, even if `next_frame` panics.
+pub fn play<F: FnMut(usize) -> Option<Frame>>(next_frame: F, delay: Duration) -> io::Result<usize> {
+    let (sender, receiver) = mpsc::channel();
+    let _session = Session::start(sender)?;
+    Player::new(next_frame, delay).run(&receiver, &mut io::stdout().lock())
+}
@@ -0,0 +1,391 @@
+//! The heightmap from day 9, along with the ways of carving it up into
+//! basins.
+
+use std::{collections::VecDeque, mem::MaybeUninit};
+
+use itertools::Itertools;
+
+const NEIGHBOR_DELTAS: [(isize, isize); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+
+fn neighboring_points(i: isize, j: isize) -> [(usize, usize); 4] {
+    let mut neighbors: [MaybeUninit<(usize, usize)>; 4] =
+        unsafe { MaybeUninit::uninit().assume_init() };
+    for (n, delta) in NEIGHBOR_DELTAS.iter().enumerate() {
+        neighbors[n] = MaybeUninit::new(((i + delta.0) as usize, (j + delta.1) as usize));
+    }
+    unsafe { std::mem::transmute::<_, [(usize, usize); 4]>(neighbors) }
+}
+
+#[derive(Debug)]
+pub struct Grid(pub Vec<Vec<u32>>);
+
+impl From<&str> for Grid {
+    fn from(input: &str) -> Self {
+        let grid = input
+            .lines()
+            .map(|line| line.chars().map(|c| c.to_digit(10).unwrap()).collect_vec())
+            .collect_vec();
+        Grid(grid)
+    }
+}
+
+impl Grid {
+    pub fn get(&self, i: isize, j: isize) -> Option<u32> {
+        self.0
+            .get(i as usize)
+            .and_then(|v| v.get(j as usize).copied().or(None))
+    }
+
+    pub fn neighbors(&self, i: isize, j: isize) -> Vec<u32> {
+        neighboring_points(i, j)
+            .iter()
+            .filter_map(|(r, c)| self.get(*r as isize, *c as isize))
+            .collect()
+    }
+
+    pub fn risk_score(&self, i: usize, j: usize) -> u32 {
+        let (i, j) = (i as isize, j as isize);
+        let x = self.get(i, j).unwrap();
+        if self.neighbors(i, j).iter().all(|v| *v > x) {
+            x + 1
+        } else {
+            0
+        }
+    }
+
+    pub fn total_risk(&self) -> u32 {
+        let mut risk = 0;
+        for i in 0..self.0.len() {
+            for j in 0..self.0.get(i).unwrap().len() {
+                risk += self.risk_score(i, j);
+            }
+        }
+        risk
+    }
+
+    pub fn low_points(&self) -> Vec<(usize, usize)> {
+        let mut low_points = vec![];
+        for i in 0..self.0.len() {
+            for j in 0..self.0.get(i).unwrap().len() {
+                if self.risk_score(i, j) != 0 {
+                    low_points.push((i, j));
+                }
+            }
+        }
+        low_points
+    }
+
+    /// Labels every cell that isn't a 9 with the basin it belongs to, using
+    /// a breadth-first flood fill from each unlabelled cell in turn.
+    pub fn basins(&self) -> Basins {
+        let mut labels = self.0.iter().map(|row| vec![None; row.len()]).collect_vec();
+        let mut basins = Vec::new();
+        for i in 0..self.0.len() {
+            for j in 0..self.0[i].len() {
+                if labels[i][j].is_some() || self.0[i][j] == 9 {
+                    continue;
+                }
+                let id = basins.len();
+                let mut basin = Basin {
+                    size: 0,
+                    low_point: (i, j),
+                    bounds: ((i, j), (i, j)),
+                };
+                let mut queue = VecDeque::new();
+                labels[i][j] = Some(id);
+                queue.push_back((i, j));
+                while let Some((r, c)) = queue.pop_front() {
+                    basin.add(r, c);
+                    if self.0[r][c] < self.0[basin.low_point.0][basin.low_point.1] {
+                        basin.low_point = (r, c);
+                    }
+                    for (nr, nc) in neighboring_points(r as isize, c as isize) {
+                        if let Some(height) = self.get(nr as isize, nc as isize) {
+                            if height < 9 && labels[nr][nc].is_none() {
+                                labels[nr][nc] = Some(id);
+                                queue.push_back((nr, nc));
+                            }
+                        }
+                    }
+                }
+                basins.push(basin);
+            }
+        }
+        Basins { labels, basins }
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.0.len()).flat_map(move |i| (0..self.0[i].len()).map(move |j| (i, j)))
+    }
+
+    /// The lowest neighbor of a cell, if it is strictly lower than the cell
+    /// itself. Ties go to whichever comes first in `NEIGHBOR_DELTAS`.
+    pub fn downhill(&self, i: usize, j: usize) -> Option<(usize, usize)> {
+        let height = self.0[i][j];
+        neighboring_points(i as isize, j as isize)
+            .into_iter()
+            .filter_map(|(r, c)| self.get(r as isize, c as isize).map(|h| (h, (r, c))))
+            .filter(|(h, _)| *h < height)
+            .min_by_key(|(h, _)| *h)
+            .map(|(_, point)| point)
+    }
+
+    /// Groups connected cells of the same height into plateaus, using the
+    /// same flood fill as `basins`
+    fn plateaus(&self) -> Plateaus {
+        let mut labels = self.0.iter().map(|row| vec![None; row.len()]).collect_vec();
+        let mut plateaus = Vec::new();
+        for (i, j) in self.cells() {
+            if labels[i][j].is_some() {
+                continue;
+            }
+            let id = plateaus.len();
+            let mut plateau = Vec::new();
+            let mut queue = VecDeque::new();
+            labels[i][j] = Some(id);
+            queue.push_back((i, j));
+            while let Some((r, c)) = queue.pop_front() {
+                plateau.push((r, c));
+                for (nr, nc) in neighboring_points(r as isize, c as isize) {
+                    if self.get(nr as isize, nc as isize) == Some(self.0[i][j])
+                        && labels[nr][nc].is_none()
+                    {
+                        labels[nr][nc] = Some(id);
+                        queue.push_back((nr, nc));
+                    }
+                }
+            }
+            plateau.sort_unstable();
+            plateaus.push(plateau);
+        }
+        Plateaus {
+            labels: labels
+                .into_iter()
+                .map(|row| row.into_iter().map(Option::unwrap).collect())
+                .collect(),
+            plateaus,
+        }
+    }
+
+    /// Lets water run from every cell down its steepest slope until it
+    /// reaches somewhere with nothing lower around it. A plateau of cells at
+    /// the same height is treated as one place: water crosses it and leaves
+    /// through its lowest exit, or, if there is none, the whole plateau is a
+    /// single sink named after its first cell. Unlike `basins`, 9s are not
+    /// walls here; they drain like anything else.
+    pub fn drain(&self) -> Drainage {
+        let Plateaus { labels, plateaus } = self.plateaus();
+        // the plateau that each one spills into, with ties going to the first
+        // cell in reading order
+        let spills = plateaus
+            .iter()
+            .map(|plateau| {
+                plateau
+                    .iter()
+                    .filter_map(|&(i, j)| self.downhill(i, j))
+                    .min_by_key(|&(r, c)| self.0[r][c])
+                    .map(|(r, c)| labels[r][c])
+            })
+            .collect_vec();
+        let mut sink_of_plateau = vec![None; plateaus.len()];
+        let mut sinks: Vec<(usize, usize)> = Vec::new();
+        let mut areas: Vec<usize> = Vec::new();
+        for start in 0..plateaus.len() {
+            // follow the spills until we hit a sink or a plateau we've
+            // already resolved, then credit everything on the way to that sink
+            let mut path = Vec::new();
+            let mut current = start;
+            let sink = loop {
+                if let Some(sink) = sink_of_plateau[current] {
+                    break sink;
+                }
+                path.push(current);
+                match spills[current] {
+                    Some(next) => current = next,
+                    None => {
+                        sinks.push(plateaus[current][0]);
+                        areas.push(0);
+                        break sinks.len() - 1;
+                    }
+                }
+            };
+            for plateau in path {
+                sink_of_plateau[plateau] = Some(sink);
+                areas[sink] += plateaus[plateau].len();
+            }
+        }
+        Drainage {
+            sinks,
+            areas,
+            sink_of: labels
+                .into_iter()
+                .map(|row| {
+                    row.into_iter()
+                        .map(|plateau| sink_of_plateau[plateau].unwrap())
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// Raises the water level from 0 to 9 one step at a time. At each level
+    /// the newly submerged cells join the pools of their flooded neighbors,
+    /// and when two pools touch the shallower one is absorbed into the
+    /// deeper one.
+    pub fn flood(&self) -> Flood {
+        // cells are numbered in reading order, so rows needn't be the same
+        // length
+        let starts = self
+            .0
+            .iter()
+            .scan(0, |start, row| {
+                let this = *start;
+                *start += row.len();
+                Some(this)
+            })
+            .collect_vec();
+        let index = |(i, j): (usize, usize)| starts[i] + j;
+        let mut parent = (0..self.cells().count()).collect_vec();
+        let mut flooded = vec![false; parent.len()];
+        // the deepest point and area of each pool, kept on its root
+        let low_points = self.cells().collect_vec();
+        let mut sizes = vec![1; parent.len()];
+        let mut merges = Vec::new();
+        let mut pools = Vec::new();
+        for level in 0..=9 {
+            let rising = self
+                .cells()
+                .filter(|(i, j)| self.0[*i][*j] == level)
+                .collect_vec();
+            for cell in &rising {
+                flooded[index(*cell)] = true;
+            }
+            for &(i, j) in &rising {
+                for (r, c) in neighboring_points(i as isize, j as isize) {
+                    if self.get(r as isize, c as isize).is_none() || !flooded[index((r, c))] {
+                        continue;
+                    }
+                    let a = find(&mut parent, index((i, j)));
+                    let b = find(&mut parent, index((r, c)));
+                    if a == b {
+                        continue;
+                    }
+                    let depth = |root: usize| {
+                        let (r, c) = low_points[root];
+                        (self.0[r][c], low_points[root])
+                    };
+                    let (deeper, shallower) = if depth(a) <= depth(b) { (a, b) } else { (b, a) };
+                    // pools that only appeared at this level are just part of
+                    // the rising water, not basins that are joining up
+                    if depth(shallower).0 < level {
+                        merges.push(Merge {
+                            level,
+                            survivor: low_points[deeper],
+                            absorbed: low_points[shallower],
+                            size: sizes[deeper] + sizes[shallower],
+                        });
+                    }
+                    parent[shallower] = deeper;
+                    sizes[deeper] += sizes[shallower];
+                }
+            }
+            let mut snapshot = self
+                .cells()
+                .map(index)
+                .filter(|cell| flooded[*cell])
+                .map(|cell| find(&mut parent, cell))
+                .unique()
+                .map(|root| Pool {
+                    low_point: low_points[root],
+                    size: sizes[root],
+                })
+                .collect_vec();
+            snapshot.sort_by_key(|pool| pool.low_point);
+            pools.push(snapshot);
+        }
+        Flood { merges, pools }
+    }
+}
+
+fn find(parent: &mut [usize], mut x: usize) -> usize {
+    while parent[x] != x {
+        parent[x] = parent[parent[x]];
+        x = parent[x];
+    }
+    x
+}
+
+/// `labels[i][j]` is the index into `plateaus` of the plateau containing
+/// `(i, j)`, and each plateau lists its cells in reading order.
+struct Plateaus {
+    labels: Vec<Vec<usize>>,
+    plateaus: Vec<Vec<(usize, usize)>>,
+}
+
+/// Where water ends up. `sink_of[i][j]` is the index into `sinks` of the
+/// sink that `(i, j)` drains into, and `areas[k]` is how many cells drain
+/// into sink `k`. A sink is named after its first cell, which for a flat
+/// sink is the top-left-most cell of the plateau.
+#[derive(Debug)]
+pub struct Drainage {
+    pub sinks: Vec<(usize, usize)>,
+    pub areas: Vec<usize>,
+    pub sink_of: Vec<Vec<usize>>,
+}
+
+/// A body of water at some level, named after its deepest cell
+#[derive(Debug, PartialEq, Eq)]
+pub struct Pool {
+    pub low_point: (usize, usize),
+    pub size: usize,
+}
+
+/// The moment two pools become one. `size` is the area of the combined pool
+/// at the moment they join.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Merge {
+    pub level: u32,
+    pub survivor: (usize, usize),
+    pub absorbed: (usize, usize),
+    pub size: usize,
+}
+
+/// `pools[level]` lists the separate pools once the water has reached
+/// `level`, and `merges` records, in order, when pools joined up.
+#[derive(Debug)]
+pub struct Flood {
+    pub merges: Vec<Merge>,
+    pub pools: Vec<Vec<Pool>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Basin {
+    pub size: usize,
+    pub low_point: (usize, usize),
+    /// the top-left and bottom-right corners, inclusive
+    pub bounds: ((usize, usize), (usize, usize)),
+}
+
+impl Basin {
+    fn add(&mut self, i: usize, j: usize) {
+        let ((top, left), (bottom, right)) = self.bounds;
+        self.bounds = ((top.min(i), left.min(j)), (bottom.max(i), right.max(j)));
+        self.size += 1;
+    }
+}
+
+/// The result of labelling a heightmap: `labels[i][j]` is the index into
+/// `basins` of the basin containing `(i, j)`, or `None` for a 9.
+#[derive(Debug)]
+pub struct Basins {
+    pub labels: Vec<Vec<Option<usize>>>,
+    pub basins: Vec<Basin>,
+}
+
+impl Basins {
+    pub fn largest(&self, n: usize) -> Vec<usize> {
+        let mut sizes = self.basins.iter().map(|b| b.size).collect_vec();
+        sizes.sort_unstable();
+        sizes.into_iter().rev().take(n).collect()
+    }
+}
@@ -0,0 +1,4 @@
+pub mod animate;
+pub mod heightmap;
+pub mod ocr;
+pub mod render;
@@ -1,3 +1,63 @@
+use std::{
+    env, fs,
+    io::{self, Write},
+    process,
+};
+
+use aoc_21::{heightmap::Grid, render::Picture};
+
+const USAGE: &str = "usage: aoc-21 heightmap <input> [--ppm <output> [--scale <pixels>]]";
+
+fn heightmap(args: &[String]) -> Result<(), String> {
+    let (input, options) = args.split_first().ok_or(USAGE)?;
+    let contents = fs::read_to_string(input).map_err(|e| format!("{}: {}", input, e))?;
+    let width = contents.lines().next().map_or(0, str::len);
+    for (n, line) in contents.lines().enumerate() {
+        if line.is_empty() || !line.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("{}:{}: expected a row of digits", input, n + 1));
+        }
+        if line.len() != width {
+            return Err(format!("{}:{}: expected {} digits", input, n + 1, width));
+        }
+    }
+    let grid = Grid::from(contents.as_str());
+    let picture = Picture::new(&grid);
+    let mut output = None;
+    let mut scale = 8;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--ppm" => output = Some(options.next().ok_or(USAGE)?),
+            "--scale" => {
+                scale = options
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .ok_or(USAGE)?
+            }
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let written = match output {
+        Some(path) => fs::File::create(path).and_then(|file| {
+            let mut out = io::BufWriter::new(file);
+            picture.ppm(scale, &mut out)?;
+            // dropping the writer would flush it too, but throw away any error
+            out.flush()
+        }),
+        None => picture.ansi(&mut io::stdout().lock()),
+    };
+    written.map_err(|e| e.to_string())
+}
+
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.split_first() {
+        Some((command, rest)) if command == "heightmap" => heightmap(rest),
+        _ => Err(USAGE.into()),
+    };
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,307 @@
+//! Reads the block capitals that some puzzles draw with lit cells, like the
+//! dots left on day 13's paper. There are two fonts: letters six cells tall
+//! and mostly four wide, and letters ten tall and six wide. Letters are told
+//! apart by the blank columns between them, so it doesn't matter how far
+//! apart they are drawn, or that I and Y are narrower and wider than the
+//! rest.
+
+use std::fmt;
+
+/// The lit cells of a picture, as rows
+pub type Bitmap = Vec<Vec<bool>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Font {
+    /// six tall and, apart from I and Y, four wide
+    Small,
+    /// six by ten
+    Large,
+}
+
+const SMALL: &[(char, [&str; 6])] = &[
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+const LARGE: &[(char, [&str; 10])] = &[
+    (
+        'A',
+        [
+            "..##..", ".#..#.", "#....#", "#....#", "#....#", "######", "#....#", "#....#",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'B',
+        [
+            "#####.", "#....#", "#....#", "#....#", "#####.", "#....#", "#....#", "#....#",
+            "#....#", "#####.",
+        ],
+    ),
+    (
+        'C',
+        [
+            ".####.", "#....#", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....",
+            "#....#", ".####.",
+        ],
+    ),
+    (
+        'E',
+        [
+            "######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....",
+            "#.....", "######",
+        ],
+    ),
+    (
+        'F',
+        [
+            "######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....",
+            "#.....", "#.....",
+        ],
+    ),
+    (
+        'G',
+        [
+            ".####.", "#....#", "#.....", "#.....", "#.....", "#..###", "#....#", "#....#",
+            "#...##", ".###.#",
+        ],
+    ),
+    (
+        'H',
+        [
+            "#....#", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'J',
+        [
+            "...###", "....#.", "....#.", "....#.", "....#.", "....#.", "....#.", "#...#.",
+            "#...#.", ".###..",
+        ],
+    ),
+    (
+        'K',
+        [
+            "#....#", "#...#.", "#..#..", "#.#...", "##....", "##....", "#.#...", "#..#..",
+            "#...#.", "#....#",
+        ],
+    ),
+    (
+        'L',
+        [
+            "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....",
+            "#.....", "######",
+        ],
+    ),
+    (
+        'N',
+        [
+            "#....#", "##...#", "##...#", "#.#..#", "#.#..#", "#..#.#", "#..#.#", "#...##",
+            "#...##", "#....#",
+        ],
+    ),
+    (
+        'P',
+        [
+            "#####.", "#....#", "#....#", "#....#", "#####.", "#.....", "#.....", "#.....",
+            "#.....", "#.....",
+        ],
+    ),
+    (
+        'R',
+        [
+            "#####.", "#....#", "#....#", "#....#", "#####.", "#..#..", "#...#.", "#...#.",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'X',
+        [
+            "#....#", "#....#", ".#..#.", ".#..#.", "..##..", "..##..", ".#..#.", ".#..#.",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'Z',
+        [
+            "######", ".....#", ".....#", "....#.", "...#..", "..#...", ".#....", "#.....",
+            "#.....", "######",
+        ],
+    ),
+];
+
+fn bitmap(rows: &[&str]) -> Bitmap {
+    rows.iter()
+        .map(|row| row.chars().map(|c| c == '#').collect())
+        .collect()
+}
+
+impl Font {
+    /// The font whose letters are `height` cells tall
+    pub fn of_height(height: usize) -> Option<Font> {
+        match height {
+            6 => Some(Font::Small),
+            10 => Some(Font::Large),
+            _ => None,
+        }
+    }
+
+    pub fn height(self) -> usize {
+        match self {
+            Font::Small => 6,
+            Font::Large => 10,
+        }
+    }
+
+    /// Every letter the font has, with its picture
+    pub fn glyphs(self) -> Vec<(char, Bitmap)> {
+        match self {
+            Font::Small => SMALL.iter().map(|(c, rows)| (*c, bitmap(rows))).collect(),
+            Font::Large => LARGE.iter().map(|(c, rows)| (*c, bitmap(rows))).collect(),
+        }
+    }
+
+    /// Draws `text` the way the puzzles do, with a gap of one blank column
+    /// between small letters and two between large ones. Fails with the
+    /// first character that isn't in the font.
+    pub fn render(self, text: &str) -> Result<Bitmap, char> {
+        let gap = match self {
+            Font::Small => 1,
+            Font::Large => 2,
+        };
+        let glyphs = self.glyphs();
+        let mut picture = vec![Vec::new(); self.height()];
+        for (n, c) in text.chars().enumerate() {
+            let (_, glyph) = glyphs.iter().find(|(g, _)| *g == c).ok_or(c)?;
+            for (row, line) in picture.iter_mut().zip(glyph) {
+                if n > 0 {
+                    row.extend(vec![false; gap]);
+                }
+                row.extend(line);
+            }
+        }
+        Ok(picture)
+    }
+
+    fn recognize(self, glyph: &Bitmap) -> Option<char> {
+        self.glyphs()
+            .into_iter()
+            .find(|(_, bitmap)| bitmap == glyph)
+            .map(|(c, _)| c)
+    }
+}
+
+/// Why a picture couldn't be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OcrError {
+    /// nothing was lit
+    Blank,
+    /// the text is a height neither font has
+    Height(usize),
+    /// the `position`th letter (counting from 0) isn't in the font
+    Unrecognized { position: usize, glyph: Bitmap },
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OcrError::Blank => write!(f, "there is no text to read"),
+            OcrError::Height(height) => write!(
+                f,
+                "text is {} cells tall, but the fonts are 6 or 10 tall",
+                height
+            ),
+            OcrError::Unrecognized { position, glyph } => {
+                writeln!(f, "letter {} isn't in the font:", position + 1)?;
+                for row in glyph {
+                    let row = row.iter().map(|&lit| if lit { '#' } else { '.' });
+                    writeln!(f, "{}", row.collect::<String>())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reads the text in `picture`, working out the font from its height. Blank
+/// rows and columns around the text are ignored.
+pub fn read(picture: &[Vec<bool>]) -> Result<String, OcrError> {
+    let lit_rows = picture
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| row.contains(&true))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let (top, bottom) = match (lit_rows.first(), lit_rows.last()) {
+        (Some(&top), Some(&bottom)) => (top, bottom),
+        _ => return Err(OcrError::Blank),
+    };
+    let rows = &picture[top..=bottom];
+    let font = Font::of_height(rows.len()).ok_or(OcrError::Height(rows.len()))?;
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let lit = |i: usize, j: usize| rows[i].get(j).copied().unwrap_or(false);
+    let column_lit = |j: usize| (0..rows.len()).any(|i| lit(i, j));
+    let mut text = String::new();
+    let mut j = 0;
+    while j < width {
+        if !column_lit(j) {
+            j += 1;
+            continue;
+        }
+        let start = j;
+        while j < width && column_lit(j) {
+            j += 1;
+        }
+        let glyph = (0..rows.len())
+            .map(|i| (start..j).map(|j| lit(i, j)).collect())
+            .collect();
+        match font.recognize(&glyph) {
+            Some(c) => text.push(c),
+            None => {
+                return Err(OcrError::Unrecognized {
+                    position: text.len(),
+                    glyph,
+                })
+            }
+        }
+    }
+    Ok(text)
+}
+
+/// Reads the text drawn by the lit cells at `(x, y)`
+pub fn read_dots(dots: impl IntoIterator<Item = (usize, usize)>) -> Result<String, OcrError> {
+    let dots = dots.into_iter().collect::<Vec<_>>();
+    let width = dots.iter().map(|&(x, _)| x + 1).max().unwrap_or(0);
+    let height = dots.iter().map(|&(_, y)| y + 1).max().unwrap_or(0);
+    let mut picture = vec![vec![false; width]; height];
+    for (x, y) in dots {
+        picture[y][x] = true;
+    }
+    read(&picture)
+}
+
+/// Reads text drawn with `#` for lit cells and anything else for dark ones
+pub fn read_str(picture: &str) -> Result<String, OcrError> {
+    let picture = picture
+        .lines()
+        .map(|line| line.chars().map(|c| c == '#').collect())
+        .collect::<Vec<_>>();
+    read(&picture)
+}
@@ -0,0 +1,116 @@
+//! Pictures of heightmaps, either as coloured blocks in the terminal or as a
+//! PPM image. Every basin gets its own hue, shaded darker the higher the
+//! ground, ridges (9s) are left nearly black, and low points are marked.
+
+use std::io::{self, Write};
+
+use crate::heightmap::{Basins, Grid};
+
+type Rgb = (u8, u8, u8);
+
+const RIDGE: Rgb = (24, 24, 24);
+const LOW_POINT: Rgb = (255, 255, 255);
+
+/// Spreads hues around the colour wheel by the golden ratio so that
+/// neighbouring basin ids don't end up looking alike
+fn hue(basin: usize) -> f64 {
+    (basin as f64 * 0.618_033_988_75).fract()
+}
+
+fn hsv(h: f64, s: f64, v: f64) -> Rgb {
+    let sector = (h * 6.0).floor();
+    let f = h * 6.0 - sector;
+    let (p, q, t) = (v * (1.0 - s), v * (1.0 - f * s), v * (1.0 - (1.0 - f) * s));
+    let (r, g, b) = match sector as u32 % 6 {
+        0 => (v, t, p),
+        1 => (q, v, p),
+        2 => (p, v, t),
+        3 => (p, q, v),
+        4 => (t, p, v),
+        _ => (v, p, q),
+    };
+    let byte = |x: f64| (x * 255.0).round() as u8;
+    (byte(r), byte(g), byte(b))
+}
+
+/// The colour of a single cell, ignoring low point markers
+pub fn color(basin: Option<usize>, height: u32) -> Rgb {
+    match basin {
+        Some(id) => hsv(hue(id), 0.7, 1.0 - height as f64 * 0.08),
+        None => RIDGE,
+    }
+}
+
+/// A heightmap together with its basin labels, ready to draw
+pub struct Picture<'a> {
+    grid: &'a Grid,
+    basins: Basins,
+    /// `low_points[i][j]` is whether `(i, j)` is a low point
+    low_points: Vec<Vec<bool>>,
+}
+
+impl<'a> Picture<'a> {
+    pub fn new(grid: &'a Grid) -> Self {
+        let mut low_points = grid
+            .0
+            .iter()
+            .map(|row| vec![false; row.len()])
+            .collect::<Vec<_>>();
+        for (i, j) in grid.low_points() {
+            low_points[i][j] = true;
+        }
+        Picture {
+            grid,
+            basins: grid.basins(),
+            low_points,
+        }
+    }
+
+    fn cell(&self, i: usize, j: usize) -> Rgb {
+        color(self.basins.labels[i][j], self.grid.0[i][j])
+    }
+
+    /// Two terminal columns per cell so that the map comes out roughly square.
+    /// Low points are drawn as `()` on top of their basin's colour.
+    pub fn ansi(&self, out: &mut impl Write) -> io::Result<()> {
+        for (i, row) in self.grid.0.iter().enumerate() {
+            for j in 0..row.len() {
+                let (r, g, b) = self.cell(i, j);
+                write!(out, "\x1b[48;2;{};{};{}m", r, g, b)?;
+                if self.low_points[i][j] {
+                    let (r, g, b) = LOW_POINT;
+                    write!(out, "\x1b[38;2;{};{};{}m()", r, g, b)?;
+                } else {
+                    write!(out, "  ")?;
+                }
+            }
+            writeln!(out, "\x1b[0m")?;
+        }
+        Ok(())
+    }
+
+    /// A binary (P6) PPM with each cell drawn as a `scale` by `scale` square.
+    /// Low points get a white dot in the middle of their square.
+    pub fn ppm(&self, scale: usize, out: &mut impl Write) -> io::Result<()> {
+        let height = self.grid.0.len();
+        let width = self.grid.0.first().map_or(0, Vec::len);
+        writeln!(out, "P6\n{} {}\n255", width * scale, height * scale)?;
+        let dot = scale / 4..scale - scale / 4;
+        for i in 0..height {
+            for y in 0..scale {
+                for j in 0..width {
+                    let low = self.low_points[i][j];
+                    for x in 0..scale {
+                        let (r, g, b) = if low && dot.contains(&x) && dot.contains(&y) {
+                            LOW_POINT
+                        } else {
+                            self.cell(i, j)
+                        };
+                        out.write_all(&[r, g, b])?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
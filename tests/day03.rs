@@ -49,10 +49,7 @@ fn oxygen_rating<const N: usize>(mut data: Vec<[usize; N]>) -> [usize; N] {
     let mut index = 0;
     while data.len() != 1 {
         let most_common_bit = most_common(&data)[index];
-        data = data
-            .into_iter()
-            .filter(|num| num[index] == most_common_bit)
-            .collect();
+        data.retain(|num| num[index] == most_common_bit);
         index = (index + 1) % N;
     }
     *data.first().unwrap()
@@ -62,10 +59,7 @@ fn co2_rating<const N: usize>(mut data: Vec<[usize; N]>) -> [usize; N] {
     let mut index = 0;
     while data.len() != 1 {
         let least_common_bit = inverse(&most_common(&data))[index];
-        data = data
-            .into_iter()
-            .filter(|num| num[index] == least_common_bit)
-            .collect();
+        data.retain(|num| num[index] == least_common_bit);
         index = (index + 1) % N;
     }
     *data.first().unwrap()
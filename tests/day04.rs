@@ -1,3 +1,7 @@
+use std::{sync::mpsc, time::Duration};
+
+use aoc_21::animate::{self, Cell, Command, Frame, Player};
+
 const INPUT: &str = include_str!("res/04.txt");
 
 #[derive(Debug)]
@@ -48,13 +52,23 @@ impl Board {
                 .board
                 .iter()
                 .map(|row| row[i])
-                .map(|(_, marked)| marked)
-                .all(|b| b);
+                .all(|(_, marked)| marked);
             if col {
                 return true;
             }
         }
-        return false;
+        false
+    }
+
+    /// Marks `number` on a board that hasn't won yet, and returns the
+    /// board's final score if that makes it win
+    fn call(&mut self, number: u32) -> Option<u32> {
+        if self.completed {
+            return None;
+        }
+        self.mark(number);
+        self.completed = self.bingo();
+        self.completed.then(|| self.score() * number)
     }
 
     fn score(&self) -> u32 {
@@ -91,6 +105,119 @@ fn parse_input() -> (Vec<u32>, Vec<Board>) {
     (numbers, boards)
 }
 
+/// Every board side by side, `per_row` to a row, with marked numbers
+/// highlighted and boards that have already won drawn darker
+fn frame(boards: &[Board], drawn: Option<u32>, per_row: usize) -> Frame {
+    let mut cells = Vec::new();
+    for band in boards.chunks(per_row) {
+        for i in 0..5 {
+            let mut row = Vec::new();
+            for board in band {
+                for (val, marked) in board.board[i] {
+                    for glyph in format!("{:>2} ", val).chars() {
+                        row.push(match (marked, board.completed) {
+                            (true, _) => Cell::highlight(glyph),
+                            (false, true) => Cell::shade(glyph, 0, 1),
+                            (false, false) => Cell::shade(glyph, 1, 2),
+                        });
+                    }
+                }
+                row.push(Cell::shade(' ', 0, 1));
+            }
+            cells.push(row);
+        }
+        cells.push(Vec::new());
+    }
+    let caption = match drawn {
+        Some(number) => format!("drew {}", number),
+        None => "ready".into(),
+    };
+    Frame { cells, caption }
+}
+
+/// Frames for the whole game, one per number drawn, starting from the boards
+/// as parsed
+fn frames(
+    numbers: Vec<u32>,
+    mut boards: Vec<Board>,
+    per_row: usize,
+) -> impl FnMut(usize) -> Option<Frame> {
+    move |step| {
+        let drawn = match step {
+            0 => None,
+            _ => Some(*numbers.get(step - 1)?),
+        };
+        if let Some(number) = drawn {
+            for board in boards.iter_mut() {
+                board.call(number);
+            }
+        }
+        Some(frame(&boards, drawn, per_row))
+    }
+}
+
+#[test]
+fn test_playback() {
+    let (numbers, boards) = parse_input();
+    let (sender, receiver) = mpsc::channel();
+    for _ in 0..5 {
+        sender.send(Command::Step).unwrap();
+    }
+    sender.send(Command::Quit).unwrap();
+    let mut frames = frames(numbers.clone(), boards, 10);
+    let mut last = None;
+    let mut player = Player::new(
+        |step| {
+            let frame = frames(step)?;
+            last = Some(frame.clone());
+            Some(frame)
+        },
+        Duration::from_millis(100),
+    )
+    .paused();
+    assert_eq!(6, player.run(&receiver, &mut Vec::new()).unwrap());
+    let last = last.unwrap();
+    assert_eq!(format!("drew {}", numbers[4]), last.caption);
+    // the same five numbers marked by hand
+    let (_, mut boards) = parse_input();
+    for &number in &numbers[..5] {
+        for board in boards.iter_mut() {
+            board.call(number);
+        }
+    }
+    let marked = boards
+        .iter()
+        .flat_map(|board| board.board.iter().flatten())
+        .filter(|(_, marked)| *marked)
+        .count();
+    let highlighted = last
+        .cells
+        .iter()
+        .flatten()
+        .filter(|cell| **cell == Cell::highlight(cell.glyph))
+        .count();
+    // three highlighted cells per number, counting the space after it
+    assert_eq!(marked * 3, highlighted);
+}
+
+#[test]
+fn test_playback_to_the_end() {
+    let (numbers, boards) = parse_input();
+    let count = numbers.len();
+    let mut player = Player::new(frames(numbers, boards, 10), Duration::ZERO);
+    let (_sender, receiver) = mpsc::channel();
+    assert_eq!(count + 1, player.run(&receiver, &mut Vec::new()).unwrap());
+}
+
+/// Run with `cargo test --test day04 watch -- --ignored --nocapture` from a
+/// terminal: space pauses, `n` steps, `+`/`-` change the speed and `q` quits
+#[test]
+#[ignore]
+fn watch() {
+    let (numbers, boards) = parse_input();
+    animate::play(frames(numbers, boards, 10), Duration::from_millis(300)).unwrap();
+}
+
 #[test]
 fn part1() {
     let (numbers, mut boards) = parse_input();
@@ -113,13 +240,8 @@ fn part2() {
     let (numbers, mut boards) = parse_input();
     let mut winners: Vec<u32> = Vec::new();
     for number in numbers {
-        for board in boards.iter_mut().filter(|board| board.completed == false) {
-            board.mark(number);
-            if board.bingo() {
-                let score = board.score() * number;
-                board.completed = true;
-                winners.push(score);
-            }
+        for board in boards.iter_mut() {
+            winners.extend(board.call(number));
         }
     }
     println!("Day 4, part 2: {:?}", winners.last());
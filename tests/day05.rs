@@ -42,7 +42,7 @@ impl Line {
     }
 }
 
-impl<'a> IntoIterator for &'a Line {
+impl IntoIterator for &Line {
     type Item = Point;
 
     type IntoIter = LineIterator;
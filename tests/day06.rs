@@ -1,4 +1,9 @@
+use std::{sync::mpsc, time::Duration};
+
+use aoc_21::animate::{self, Cell, Command, Frame, Player};
+
 const INPUT: &str = include_str!("res/06.txt");
+const EXAMPLE: &str = include_str!("res/06ex.txt");
 
 #[derive(Debug)]
 struct Fish(u32);
@@ -27,17 +32,133 @@ impl Ocean {
         }
         self.0.append(&mut new_fish);
     }
+
+    fn parse(input: &str) -> Ocean {
+        Ocean(
+            input
+                .trim()
+                .split(',')
+                .map(str::parse)
+                .map(Result::unwrap)
+                .map(Fish)
+                .collect(),
+        )
+    }
+}
+
+/// The same fish counted by timer instead of one by one, which stays small
+/// however many fish there are
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct School([u64; 9]);
+
+impl School {
+    fn tick(&mut self) {
+        // not my solution... thank you r/adventofcode...
+        self.0.rotate_left(1);
+        self.0[6] += self.0[8];
+    }
+
+    fn len(&self) -> u64 {
+        self.0.iter().sum()
+    }
+}
+
+impl From<&Ocean> for School {
+    fn from(ocean: &Ocean) -> Self {
+        let mut counts = [0; 9];
+        for fish in &ocean.0 {
+            counts[fish.0 as usize] += 1;
+        }
+        School(counts)
+    }
+}
+
+/// The population bucketed by timer as a bar chart, one row per timer value,
+/// with the longest bar `width` cells wide
+fn frame(school: &School, day: usize, width: usize) -> Frame {
+    let max = school.0.iter().copied().max().unwrap_or(0).max(1);
+    let cells = school
+        .0
+        .iter()
+        .enumerate()
+        .map(|(timer, &count)| {
+            let bar = (count as u128 * width as u128 / max as u128) as usize;
+            let mut row = vec![Cell::shade(
+                char::from_digit(timer as u32, 10).unwrap(),
+                0,
+                1,
+            )];
+            row.push(Cell::shade(' ', 0, 1));
+            row.extend((0..bar).map(|_| Cell::shade(' ', 8 - timer as u32, 8)));
+            row
+        })
+        .collect();
+    let caption = format!("day {}: {} fish", day, school.len());
+    Frame { cells, caption }
+}
+
+/// Frames for `days` days, starting from `school` as it is
+fn frames(mut school: School, days: usize, width: usize) -> impl FnMut(usize) -> Option<Frame> {
+    move |day| {
+        if day > days {
+            return None;
+        }
+        if day > 0 {
+            school.tick();
+        }
+        Some(frame(&school, day, width))
+    }
+}
+
+#[test]
+fn test_playback() {
+    let school = School::from(&Ocean::parse(EXAMPLE));
+    let first = frame(&school, 0, 60);
+    assert_eq!(9, first.cells.len());
+    assert_eq!(2 + 30, first.cells[1].len());
+    assert_eq!(2 + 60, first.cells[3].len());
+    assert_eq!("day 0: 5 fish", first.caption);
+
+    let (sender, receiver) = mpsc::channel();
+    sender.send(Command::Faster).unwrap();
+    let mut captions = Vec::new();
+    let mut frames = frames(school, 18, 60);
+    let mut player = Player::new(
+        |day| {
+            let frame = frames(day)?;
+            captions.push(frame.caption.clone());
+            Some(frame)
+        },
+        Duration::from_millis(20),
+    );
+    assert_eq!(19, player.run(&receiver, &mut Vec::new()).unwrap());
+    assert_eq!(Some(&"day 18: 26 fish".to_string()), captions.last());
+}
+
+/// Run with `cargo test --test day06 watch -- --ignored --nocapture` from a
+/// terminal: space pauses, `n` steps, `+`/`-` change the speed and `q` quits
+#[test]
+#[ignore]
+fn watch() {
+    let school = School::from(&Ocean::parse(INPUT));
+    animate::play(frames(school, 256, 60), Duration::from_millis(100)).unwrap();
+}
+
+#[test]
+fn test_school() {
+    let mut ocean = Ocean::parse(EXAMPLE);
+    let mut school = School::from(&ocean);
+    for _ in 0..80 {
+        ocean.tick();
+        school.tick();
+        assert_eq!(School::from(&ocean), school);
+    }
+    assert_eq!(5934, school.len());
 }
 
 #[test]
 fn part1() {
-    let fish: Vec<_> = INPUT
-        .split(',')
-        .map(str::parse)
-        .map(Result::unwrap)
-        .map(Fish)
-        .collect();
-    let mut ocean = Ocean(fish);
+    let mut ocean = Ocean::parse(INPUT);
     for _ in 0..80 {
         ocean.tick();
     }
@@ -56,20 +177,9 @@ fn fish_test() {
 
 #[test]
 fn part2() {
-    let fish: Vec<usize> = INPUT
-        .split(',')
-        .map(str::parse)
-        .map(Result::unwrap)
-        .collect();
-    // not my solution... thank you r/adventofcode...
-    let mut counts: [u64; 9] = [0; 9];
-    for f in fish.iter() {
-        counts[*f] += 1;
-    }
+    let mut school = School::from(&Ocean::parse(INPUT));
     for _ in 0..256 {
-        counts.rotate_left(1);
-        counts[6] += counts[8];
+        school.tick();
     }
-    let sum: u64 = counts.iter().sum();
-    println!("Day 6, part 2: {}", sum);
+    println!("Day 6, part 2: {}", school.len());
 }
@@ -1,4 +1,5 @@
 const INPUT: &str = include_str!("res/07.txt");
+const EXAMPLE: &str = include_str!("res/07ex.txt");
 
 fn optimal_score(positions: &[u32]) -> u32 {
     let max = *positions.iter().max().unwrap();
@@ -8,7 +9,7 @@ fn optimal_score(positions: &[u32]) -> u32 {
 fn score(target: u32, positions: &[u32]) -> u32 {
     positions
         .iter()
-        .map(|p| (target as i32 - *p as i32).abs() as u32)
+        .map(|p| (target as i32 - *p as i32).unsigned_abs())
         .sum()
 }
 
@@ -22,10 +23,98 @@ fn crab_score(target: u32, positions: &[u32]) -> u32 {
 }
 
 fn crab_cost(start: u32, end: u32) -> u32 {
-    let distance = (start as i32 - end as i32).abs() as u32;
+    let distance = (start as i32 - end as i32).unsigned_abs();
     (distance * (distance + 1)) / 2
 }
 
+/// How much fuel a single crab burns to move a given distance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FuelModel {
+    /// one unit of fuel per step (part 1)
+    Linear,
+    /// each step costs one more than the last (part 2)
+    Triangular,
+}
+
+impl FuelModel {
+    fn parse(token: &str) -> Option<FuelModel> {
+        match token {
+            "linear" | "l" => Some(FuelModel::Linear),
+            "triangular" | "t" => Some(FuelModel::Triangular),
+            _ => None,
+        }
+    }
+
+    fn cost(&self, distance: u64) -> u64 {
+        match self {
+            FuelModel::Linear => distance,
+            FuelModel::Triangular => (distance * (distance + 1)) / 2,
+        }
+    }
+}
+
+/// A group of crabs sitting at the same position. Entries in the input are
+/// either a bare position (`16`), a weighted position (`16:3`), or a weighted
+/// position with its own fuel model (`16:3:triangular`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Crabs {
+    position: u32,
+    count: u64,
+    model: Option<FuelModel>,
+}
+
+impl Crabs {
+    fn parse(token: &str) -> Option<Crabs> {
+        let mut fields = token.trim().split(':');
+        let position = fields.next()?.parse().ok()?;
+        let count = match fields.next() {
+            Some(count) => count.parse().ok()?,
+            None => 1,
+        };
+        let model = match fields.next() {
+            Some(model) => Some(FuelModel::parse(model)?),
+            None => None,
+        };
+        if fields.next().is_some() {
+            return None;
+        }
+        Some(Crabs {
+            position,
+            count,
+            model,
+        })
+    }
+
+    fn cost(&self, target: u32, default: FuelModel) -> u64 {
+        let distance = (self.position as i64 - target as i64).unsigned_abs();
+        self.model.unwrap_or(default).cost(distance) * self.count
+    }
+}
+
+fn parse_fleet(input: &str) -> Option<Vec<Crabs>> {
+    input.trim().split(',').map(Crabs::parse).collect()
+}
+
+/// The total fuel needed to align the whole fleet on every target between the
+/// leftmost and rightmost crab, as `(target, fuel)` pairs. Crabs without their
+/// own fuel model use `default`.
+fn cost_curve(fleet: &[Crabs], default: FuelModel) -> Vec<(u32, u64)> {
+    let min = fleet.iter().map(|c| c.position).min().unwrap_or(0);
+    let max = fleet.iter().map(|c| c.position).max().unwrap_or(0);
+    (min..=max)
+        .map(|target| {
+            let fuel = fleet.iter().map(|c| c.cost(target, default)).sum();
+            (target, fuel)
+        })
+        .collect()
+}
+
+fn optimal_alignment(fleet: &[Crabs], default: FuelModel) -> Option<(u32, u64)> {
+    cost_curve(fleet, default)
+        .into_iter()
+        .min_by_key(|(_, fuel)| *fuel)
+}
+
 #[test]
 fn part1() {
     let positions: Vec<u32> = INPUT
@@ -39,6 +128,38 @@ fn part1() {
 #[test]
 fn test_crab_cost() {
     assert_eq!(66, crab_cost(16, 5));
+    assert_eq!(66, FuelModel::Triangular.cost(11));
+}
+
+#[test]
+fn test_weighted_fleet() {
+    let plain = parse_fleet(EXAMPLE).unwrap();
+    let weighted = parse_fleet("16,1,2:3,0,4,7,1,14").unwrap();
+    assert_eq!(Some((2, 37)), optimal_alignment(&plain, FuelModel::Linear));
+    assert_eq!(
+        Some((2, 37)),
+        optimal_alignment(&weighted, FuelModel::Linear)
+    );
+    assert_eq!(
+        Some((5, 168)),
+        optimal_alignment(&weighted, FuelModel::Triangular)
+    );
+    assert_eq!(
+        cost_curve(&plain, FuelModel::Triangular),
+        cost_curve(&weighted, FuelModel::Triangular)
+    );
+}
+
+#[test]
+fn test_mixed_fuel_models() {
+    let fleet = parse_fleet("0:2:linear,10:1:t").unwrap();
+    let curve = cost_curve(&fleet, FuelModel::Linear);
+    assert_eq!(11, curve.len());
+    assert_eq!((0, 55), curve[0]);
+    assert_eq!((10, 20), curve[10]);
+    assert_eq!(Some((8, 19)), optimal_alignment(&fleet, FuelModel::Linear));
+    assert_eq!(None, parse_fleet("1:2:quadratic"));
+    assert_eq!(None, parse_fleet("1:x"));
 }
 
 #[test]
@@ -49,4 +170,9 @@ fn part2() {
         .map(Result::unwrap)
         .collect();
     println!("Day 7, part 2: {}", optimal_crab_cost(&positions));
+    let fleet = parse_fleet(INPUT).unwrap();
+    assert_eq!(
+        Some(optimal_crab_cost(&positions) as u64),
+        optimal_alignment(&fleet, FuelModel::Triangular).map(|(_, fuel)| fuel)
+    );
 }
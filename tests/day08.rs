@@ -1,6 +1,10 @@
+use std::{collections::BTreeSet, ops::Not};
+
 use itertools::Itertools;
 
 const INPUT: &str = include_str!("res/08.txt");
+const EXAMPLE: &str = include_str!("res/08ex.txt");
+const ALPHA14: &str = include_str!("res/08alpha14.txt");
 
 const DISPLAY: [[bool; 7]; 10] = [
     [true, true, true, false, true, true, true],
@@ -15,6 +19,171 @@ const DISPLAY: [[bool; 7]; 10] = [
     [true, true, true, true, false, true, true],
 ];
 
+/// The letters a through f as they're usually drawn on a seven-segment
+/// display, in the same segment order as `DISPLAY`
+const HEX_LETTERS: [(char, [bool; 7]); 6] = [
+    ('A', [true, true, true, true, true, true, false]),
+    ('b', [false, true, false, true, true, true, true]),
+    ('C', [true, true, false, false, true, false, true]),
+    ('d', [false, false, true, true, true, true, true]),
+    ('E', [true, true, false, true, true, false, true]),
+    ('F', [true, true, false, true, true, false, false]),
+];
+
+/// The glyphs a display is able to show. Each glyph is stored as a bitmask of
+/// its lit segments, where bit `i` is segment `i`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GlyphSet {
+    segments: usize,
+    glyphs: Vec<(char, u32)>,
+}
+
+fn bitmask(lit: &[bool]) -> u32 {
+    lit.iter()
+        .enumerate()
+        .filter(|(_, on)| **on)
+        .fold(0, |mask, (i, _)| mask | 1 << i)
+}
+
+impl GlyphSet {
+    fn decimal() -> Self {
+        let glyphs = DISPLAY
+            .iter()
+            .enumerate()
+            .map(|(digit, lit)| (char::from_digit(digit as u32, 10).unwrap(), bitmask(lit)))
+            .collect();
+        GlyphSet {
+            segments: 7,
+            glyphs,
+        }
+    }
+
+    fn hexadecimal() -> Self {
+        let mut set = GlyphSet::decimal();
+        for (letter, lit) in HEX_LETTERS {
+            set.glyphs.push((letter, bitmask(&lit)));
+        }
+        set
+    }
+
+    /// Reads a table with one glyph per line: the glyph itself, then a space,
+    /// then a `0`/`1` string saying which segments are lit, e.g. `7 1010010`.
+    /// Blank lines and lines starting with `#` are skipped.
+    fn parse(table: &str) -> Result<Self, String> {
+        let mut segments = None;
+        let mut glyphs: Vec<(char, u32)> = Vec::new();
+        let rows = table
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+        for (n, line) in rows {
+            let (glyph, lit) = line
+                .trim()
+                .split_once(' ')
+                .ok_or(format!("line {}: expected a glyph and its segments", n + 1))?;
+            let mut chars = glyph.chars();
+            let glyph = match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(format!("line {}: `{}` is not a single glyph", n + 1, glyph)),
+            };
+            let lit = lit
+                .trim()
+                .chars()
+                .map(|c| match c {
+                    '1' => Ok(true),
+                    '0' => Ok(false),
+                    _ => Err(format!("line {}: unexpected segment value `{}`", n + 1, c)),
+                })
+                .collect::<Result<Vec<bool>, String>>()?;
+            if lit.len() > 26 {
+                return Err(format!("line {}: too many segments", n + 1));
+            }
+            match segments {
+                Some(len) if len != lit.len() => {
+                    return Err(format!(
+                        "line {}: expected {} segments but found {}",
+                        n + 1,
+                        len,
+                        lit.len()
+                    ))
+                }
+                _ => segments = Some(lit.len()),
+            }
+            let mask = bitmask(&lit);
+            if let Some((other, _)) = glyphs.iter().find(|(_, m)| *m == mask) {
+                return Err(format!(
+                    "line {}: `{}` looks the same as `{}`",
+                    n + 1,
+                    glyph,
+                    other
+                ));
+            }
+            glyphs.push((glyph, mask));
+        }
+        match segments {
+            Some(segments) => Ok(GlyphSet { segments, glyphs }),
+            None => Err("the glyph table is empty".into()),
+        }
+    }
+
+    fn glyph(&self, mask: u32) -> Option<char> {
+        self.glyphs
+            .iter()
+            .find(|(_, m)| *m == mask)
+            .map(|(glyph, _)| *glyph)
+    }
+
+    /// The wires are labelled with consecutive letters starting at `a`
+    fn wires(&self) -> Vec<char> {
+        ('a'..).take(self.segments).collect()
+    }
+}
+
+type Segments = BTreeSet<char>;
+
+fn segments(pattern: &[char]) -> Segments {
+    pattern.iter().copied().collect()
+}
+
+fn show(segments: &Segments) -> String {
+    segments.iter().collect()
+}
+
+/// The wiring found by `Mapping::deduce`, along with a human-readable account
+/// of how each digit and segment was pinned down.
+#[derive(Debug)]
+struct Deduction {
+    wiring: [char; 7],
+    steps: Vec<String>,
+}
+
+/// One of the patterns recorded on a line, identified by which side of the
+/// `|` it was on and its position there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Observation {
+    Input(usize),
+    Output(usize),
+}
+
+/// What can be said about a line once every wiring has been checked against it
+#[derive(Debug, PartialEq, Eq)]
+enum Report {
+    /// exactly one wiring fits
+    Solved(Vec<char>),
+    /// several wirings fit; `differing` lists the output positions that decode
+    /// to different glyphs depending on which wiring is chosen
+    Ambiguous {
+        candidates: Vec<Vec<char>>,
+        differing: Vec<usize>,
+    },
+    /// no wiring fits; `culprits` are the observations that could be the
+    /// garbled one, i.e. those without which some wiring fits everything
+    /// else. It's empty if no single pattern explains the line.
+    Inconsistent {
+        culprits: Vec<(Observation, String)>,
+    },
+}
+
 #[derive(Debug)]
 struct Mapping {
     inputs: Vec<Vec<char>>,
@@ -37,53 +206,320 @@ impl From<&str> for Mapping {
 }
 
 impl Mapping {
-    fn unscramble(&self) -> [char; 7] {
-        let ans = vec!['a', 'b', 'c', 'd', 'e', 'f', 'g']
+    /// The first wiring that displays every input as one of `glyphs`, if any
+    /// does
+    fn unscramble(&self, glyphs: &GlyphSet) -> Option<Vec<char>> {
+        Mapping::search(glyphs, &self.inputs.iter().collect_vec(), 1).pop()
+    }
+
+    fn valid(&self, glyphs: &GlyphSet, wiring: &[char]) -> bool {
+        self.inputs
             .iter()
-            .permutations(7)
-            .filter(|combo| self.valid(combo))
-            .next()
-            .unwrap()
+            .all(|pattern| Mapping::glyph(glyphs, pattern, wiring).is_some())
+    }
+
+    /// The glyph `pattern` shows when segment `i` is driven by `wiring[i]`
+    fn glyph(glyphs: &GlyphSet, pattern: &[char], wiring: &[char]) -> Option<char> {
+        let mut mask = 0;
+        for c in pattern {
+            mask |= 1 << wiring.iter().position(|w| w == c)?;
+        }
+        glyphs.glyph(mask)
+    }
+
+    /// Works out the wiring of a decimal display from the segment sets of the
+    /// observed patterns rather than by searching. The digits with a unique segment count
+    /// (1, 4, 7 and 8) are found first, and everything else follows from
+    /// intersections and differences with those. Returns `None` if the
+    /// observations don't contain enough distinct digits to finish, or if the
+    /// wiring they point to doesn't actually display every input as a digit
+    /// (the rules above only look at some of the patterns, so a garbled one
+    /// can slip through).
+    fn deduce(&self) -> Option<Deduction> {
+        let patterns = self.inputs.iter().map(|p| segments(p)).collect_vec();
+        let mut steps = Vec::new();
+        let mut digits: [Option<&Segments>; 10] = [None; 10];
+
+        // the digits that can be identified by their length alone
+        for (digit, len) in [(1, 2), (4, 4), (7, 3), (8, 7)] {
+            let found = patterns.iter().find(|p| p.len() == len)?;
+            steps.push(format!(
+                "{} is {} (the only digit with {} segments)",
+                digit,
+                show(found),
+                len
+            ));
+            digits[digit] = Some(found);
+        }
+        let one = digits[1]?;
+        let four = digits[4]?;
+
+        // six segments: 0, 6, and 9
+        for pattern in patterns.iter().filter(|p| p.len() == 6) {
+            let (digit, reason) = if pattern.is_superset(four) {
+                (9, "contains all of 4")
+            } else if pattern.is_superset(one) {
+                (0, "contains 1 but not 4")
+            } else {
+                (6, "does not contain 1")
+            };
+            steps.push(format!(
+                "{} is {} (six segments, {})",
+                digit,
+                show(pattern),
+                reason
+            ));
+            digits[digit] = Some(pattern);
+        }
+        let six = digits[6]?;
+
+        // five segments: 2, 3, and 5
+        for pattern in patterns.iter().filter(|p| p.len() == 5) {
+            let (digit, reason) = if pattern.is_superset(one) {
+                (3, "contains all of 1")
+            } else if pattern.is_subset(six) {
+                (5, "fits inside 6")
+            } else {
+                (2, "neither contains 1 nor fits inside 6")
+            };
+            steps.push(format!(
+                "{} is {} (five segments, {})",
+                digit,
+                show(pattern),
+                reason
+            ));
+            digits[digit] = Some(pattern);
+        }
+
+        // with the digits known, each segment is what's left over from some
+        // difference between two of them
+        let seven = digits[7]?;
+        let eight = digits[8]?;
+        let zero = digits[0]?;
+        let nine = digits[9]?;
+        let a = seven - one;
+        let c = eight - six;
+        let f = one - &c;
+        let d = eight - zero;
+        let e = eight - nine;
+        let b = &(four - one) - &d;
+        let known: Segments = [&a, &b, &c, &d, &e, &f]
             .into_iter()
-            .map(|c| *c)
-            .collect_vec();
-        ans.try_into().unwrap()
+            .flatten()
+            .copied()
+            .collect();
+        let g = eight - &known;
+        let derivations = [
+            ('a', a, "7 - 1"),
+            ('b', b, "4 - 1 - d"),
+            ('c', c, "8 - 6"),
+            ('d', d, "8 - 0"),
+            ('e', e, "8 - 9"),
+            ('f', f, "1 - c"),
+            ('g', g, "8 - everything else"),
+        ];
+        let mut wiring = [' '; 7];
+        for (i, (segment, wire, derivation)) in derivations.into_iter().enumerate() {
+            if wire.len() != 1 {
+                return None;
+            }
+            wiring[i] = *wire.iter().next()?;
+            steps.push(format!(
+                "segment {} is wire {} ({})",
+                segment, wiring[i], derivation
+            ));
+        }
+        if !self.valid(&GlyphSet::decimal(), &wiring) {
+            return None;
+        }
+        Some(Deduction { wiring, steps })
+    }
+
+    /// Finds a wiring that displays every input as one of `glyphs`. Decimal
+    /// wirings are deduced if possible; anything else, or anything the
+    /// deduction can't finish, is searched for. `None` means the line is
+    /// inconsistent, and `report` says more about why.
+    fn solve(&self, glyphs: &GlyphSet) -> Option<Vec<char>> {
+        let deduction = if *glyphs == GlyphSet::decimal() {
+            self.deduce()
+        } else {
+            None
+        };
+        match deduction {
+            Some(deduction) => Some(deduction.wiring.to_vec()),
+            None => self.unscramble(glyphs),
+        }
     }
 
-    fn valid(&self, combo: &[&char]) -> bool {
-        for digit in self.inputs.iter() {
-            let displayed = Mapping::displayed(digit, combo);
-            if !DISPLAY.contains(&displayed) {
-                return false;
+    fn observations(&self) -> impl Iterator<Item = (Observation, &Vec<char>)> {
+        let inputs = self
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (Observation::Input(i), p));
+        let outputs = self
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (Observation::Output(i), p));
+        inputs.chain(outputs)
+    }
+
+    /// Every wiring under which all of the observed patterns, inputs and
+    /// outputs alike, show up as one of `glyphs`
+    fn wirings(&self, glyphs: &GlyphSet) -> Vec<Vec<char>> {
+        let patterns = self.observations().map(|(_, p)| p).collect_vec();
+        Mapping::search(glyphs, &patterns, usize::MAX)
+    }
+
+    /// Up to `limit` wirings under which each of `patterns` shows a glyph.
+    /// Entry `i` of a wiring is the wire that drives segment `i`.
+    fn search(glyphs: &GlyphSet, patterns: &[&Vec<char>], limit: usize) -> Vec<Vec<char>> {
+        let wires = glyphs.wires();
+        let mut indices = Vec::new();
+        for pattern in patterns {
+            match pattern
+                .iter()
+                .map(|c| wires.iter().position(|w| w == c))
+                .collect::<Option<Vec<usize>>>()
+            {
+                Some(pattern) => indices.push(pattern),
+                // a wire the display doesn't have
+                None => return Vec::new(),
             }
         }
-        true
+        let mut search = Search {
+            glyphs,
+            patterns: &indices,
+            assigned: vec![None; wires.len()],
+            used: vec![false; glyphs.segments],
+            found: Vec::new(),
+            limit,
+        };
+        search.assign(0);
+        search
+            .found
+            .into_iter()
+            .map(|assigned| {
+                let mut wiring = vec![' '; glyphs.segments];
+                for (wire, segment) in assigned.into_iter().enumerate() {
+                    wiring[segment] = wires[wire];
+                }
+                wiring
+            })
+            .collect()
     }
 
-    fn displayed(digit: &[char], combo: &[&char]) -> [bool; 7] {
-        let mut displayed = [false; 7];
-        for segment in digit {
-            match combo.iter().position(|c| *c == segment) {
-                Some(index) => displayed[index] = true,
-                None => {}
+    /// The observations without which some wiring would show every other
+    /// pattern as one of `glyphs`
+    fn culprits(&self, glyphs: &GlyphSet) -> Vec<(Observation, String)> {
+        let observations = self.observations().collect_vec();
+        (0..observations.len())
+            .filter(|&i| {
+                let others = observations
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, (_, pattern))| *pattern)
+                    .collect_vec();
+                !Mapping::search(glyphs, &others, 1).is_empty()
+            })
+            .map(|i| (observations[i].0, observations[i].1.iter().collect()))
+            .collect()
+    }
+
+    fn report(&self, glyphs: &GlyphSet) -> Report {
+        let mut candidates = self.wirings(glyphs);
+        if candidates.is_empty() {
+            return Report::Inconsistent {
+                culprits: self.culprits(glyphs),
             };
         }
-        displayed
+        if candidates.len() == 1 {
+            return Report::Solved(candidates.remove(0));
+        }
+        let differing = self
+            .outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, pattern)| {
+                candidates
+                    .iter()
+                    .map(|wiring| Mapping::glyph(glyphs, pattern, wiring))
+                    .all_equal()
+                    .not()
+            })
+            .map(|(i, _)| i)
+            .collect();
+        Report::Ambiguous {
+            candidates,
+            differing,
+        }
     }
 
-    fn output(&self, combo: &[char]) -> u32 {
-        let combo = combo.iter().collect_vec();
+    /// Reads the output patterns as glyphs under the given wiring
+    fn decode(&self, glyphs: &GlyphSet, wiring: &[char]) -> Option<String> {
         self.outputs
             .iter()
-            .rev()
-            .enumerate()
-            .map(|(i, digit)| {
-                let displayed = Mapping::displayed(digit, &combo);
-                let value = DISPLAY.iter().position(|d| d == &displayed).unwrap();
-                (i, value)
-            })
-            .map(|(i, value)| value as u32 * 10u32.pow(i as u32))
-            .sum()
+            .map(|pattern| Mapping::glyph(glyphs, pattern, wiring))
+            .collect()
+    }
+
+    /// The number the outputs show under the given wiring, or `None` if one
+    /// of them isn't a decimal digit under it
+    fn output(&self, glyphs: &GlyphSet, wiring: &[char]) -> Option<u32> {
+        self.decode(glyphs, wiring)?
+            .chars()
+            .try_fold(0, |value, c| Some(value * 10 + c.to_digit(10)?))
+    }
+}
+
+/// The state of `Mapping::search`. Wires are assigned to segments one at a
+/// time, backing out as soon as some pattern can no longer be any glyph with
+/// the same number of segments.
+struct Search<'a> {
+    glyphs: &'a GlyphSet,
+    patterns: &'a [Vec<usize>],
+    /// the segment each wire drives, so far
+    assigned: Vec<Option<usize>>,
+    used: Vec<bool>,
+    found: Vec<Vec<usize>>,
+    limit: usize,
+}
+
+impl Search<'_> {
+    fn assign(&mut self, wire: usize) {
+        let plausible = self.patterns.iter().all(|pattern| {
+            let lit = pattern
+                .iter()
+                .filter_map(|w| self.assigned[*w])
+                .fold(0, |mask, segment| mask | 1 << segment);
+            self.glyphs
+                .glyphs
+                .iter()
+                .any(|(_, m)| m.count_ones() as usize == pattern.len() && m & lit == lit)
+        });
+        if !plausible {
+            return;
+        }
+        if wire == self.assigned.len() {
+            self.found
+                .push(self.assigned.iter().map(|s| s.unwrap()).collect());
+            return;
+        }
+        for segment in 0..self.used.len() {
+            if self.used[segment] {
+                continue;
+            }
+            self.used[segment] = true;
+            self.assigned[wire] = Some(segment);
+            self.assign(wire + 1);
+            self.used[segment] = false;
+            self.assigned[wire] = None;
+            if self.found.len() == self.limit {
+                return;
+            }
+        }
     }
 }
 
@@ -103,16 +539,288 @@ fn part1() {
     println!("Day 8, part 1: {}", outputs);
 }
 
+#[test]
+fn test_deduction() {
+    let decimal = GlyphSet::decimal();
+    let mapping = Mapping::from(
+        "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+    );
+    let deduction = mapping.deduce().unwrap();
+    assert_eq!(['d', 'e', 'a', 'f', 'g', 'b', 'c'], deduction.wiring);
+    assert_eq!(Some(5353), mapping.output(&decimal, &deduction.wiring));
+    for step in &deduction.steps {
+        println!("{}", step);
+    }
+    assert!(deduction
+        .steps
+        .contains(&"1 is ab (the only digit with 2 segments)".to_string()));
+}
+
+#[test]
+fn test_deduction_matches_search() {
+    for line in EXAMPLE.lines().chain(INPUT.lines()) {
+        let mapping = Mapping::from(line);
+        let deduced = mapping.deduce().unwrap().wiring;
+        assert_eq!(
+            mapping.unscramble(&GlyphSet::decimal()),
+            Some(deduced.to_vec()),
+            "{}",
+            line
+        );
+    }
+}
+
+#[test]
+fn test_deduction_fallback() {
+    // without a 4 the deduction can't get started, but the search still finds
+    // the one wiring that works
+    let decimal = GlyphSet::decimal();
+    let mapping = Mapping::from(
+        "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+    );
+    assert!(mapping.deduce().is_none());
+    let wiring = mapping.solve(&decimal).unwrap();
+    assert_eq!(Some(5353), mapping.output(&decimal, &wiring));
+}
+
+#[test]
+fn test_deduction_checks_its_wiring() {
+    // the 2 has lost its a and gained an f, which the rules for five-segment
+    // patterns can't tell, but the wiring they lead to doesn't show it
+    let mapping = Mapping::from(
+        "acedgfb cdfbe gcdfb fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+    );
+    assert!(mapping.deduce().is_none());
+}
+
+#[test]
+fn test_unique_reports() {
+    let decimal = GlyphSet::decimal();
+    for line in EXAMPLE.lines() {
+        let mapping = Mapping::from(line);
+        assert_eq!(
+            Report::Solved(mapping.unscramble(&decimal).unwrap()),
+            mapping.report(&decimal)
+        );
+    }
+}
+
+#[test]
+fn test_ambiguous_report() {
+    let decimal = GlyphSet::decimal();
+    // with only a 1 to go on, c and f could be either way around and the rest
+    // is a free-for-all, but the output is still unmistakably a 1
+    let mapping = Mapping::from("ab | ab");
+    match mapping.report(&decimal) {
+        Report::Ambiguous {
+            candidates,
+            differing,
+        } => {
+            assert_eq!(240, candidates.len());
+            assert!(differing.is_empty());
+        }
+        report => panic!("expected an ambiguous report, got {:?}", report),
+    }
+    // dropping the 4 from a complete line still pins the wiring down...
+    let mapping = Mapping::from(
+        "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+    );
+    assert_eq!(1, mapping.wirings(&decimal).len());
+    // ...but with only 1, 7 and 8 to go on, the first and third outputs could
+    // be read more than one way
+    let mapping = Mapping::from("acedgfb dab ab | cdfeb fcadb cdfeb cdbaf");
+    match mapping.report(&decimal) {
+        Report::Ambiguous {
+            candidates,
+            differing,
+        } => {
+            assert_eq!(mapping.wirings(&decimal), candidates);
+            assert!(candidates.contains(&vec!['d', 'e', 'a', 'f', 'g', 'b', 'c']));
+            assert_eq!(4, candidates.len());
+            assert_eq!(vec![0, 2], differing);
+        }
+        report => panic!("expected an ambiguous report, got {:?}", report),
+    }
+}
+
+#[test]
+fn test_inconsistent_report() {
+    let decimal = GlyphSet::decimal();
+    // a 1 garbled into a second three-segment pattern, and nothing else on
+    // the line says which of the two is the real 7
+    let mapping = Mapping::from(
+        "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb abc | cdfeb fcadb cdfeb cdbaf",
+    );
+    assert_eq!(
+        Report::Inconsistent {
+            culprits: vec![
+                (Observation::Input(4), "dab".into()),
+                (Observation::Input(9), "abc".into())
+            ],
+        },
+        mapping.report(&decimal)
+    );
+    assert!(mapping.wirings(&decimal).is_empty());
+    assert_eq!(None, mapping.solve(&decimal));
+    // a garbled 2, which isn't the first pattern to run out of wirings: the
+    // ones that fit it are only ruled out by the 3 after it
+    let mapping = Mapping::from(
+        "acedgfb cdfbe gcdfb fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+    );
+    assert_eq!(None, mapping.solve(&decimal));
+    assert_eq!(
+        Report::Inconsistent {
+            culprits: vec![(Observation::Input(2), "gcdfb".into())],
+        },
+        mapping.report(&decimal)
+    );
+    // a dropped segment in the output, which the inputs alone can't catch
+    let mapping = Mapping::from(
+        "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfe cdbaf",
+    );
+    let wiring = mapping.solve(&decimal).unwrap();
+    assert_eq!(vec!['d', 'e', 'a', 'f', 'g', 'b', 'c'], wiring);
+    assert_eq!(None, mapping.output(&decimal, &wiring));
+    assert_eq!(
+        Report::Inconsistent {
+            culprits: vec![(Observation::Output(2), "cdfe".into())],
+        },
+        mapping.report(&decimal)
+    );
+    // two garbled patterns can't be pinned on either one alone
+    let mapping = Mapping::from(
+        "acedgfb cdfbe gcdfb fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfe cdbaf",
+    );
+    assert_eq!(
+        Report::Inconsistent {
+            culprits: Vec::new()
+        },
+        mapping.report(&decimal)
+    );
+}
+
+/// Draws `text` on a display whose segment `i` is driven by `wiring[i]`, the
+/// way the puzzle input would record it
+fn scramble(glyphs: &GlyphSet, wiring: &[char], text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            let mask = glyphs.glyphs.iter().find(|(g, _)| *g == c).unwrap().1;
+            (0..glyphs.segments)
+                .filter(|i| mask & 1 << i != 0)
+                .map(|i| wiring[i])
+                .collect::<String>()
+        })
+        .join(" ")
+}
+
+#[test]
+fn test_decimal_glyph_set() {
+    let decimal = GlyphSet::decimal();
+    for line in EXAMPLE.lines() {
+        let mapping = Mapping::from(line);
+        let wiring = mapping.solve(&decimal).unwrap();
+        assert_eq!(
+            format!("{:04}", mapping.output(&decimal, &wiring).unwrap()),
+            mapping.decode(&decimal, &wiring).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_hexadecimal_glyph_set() {
+    let hex = GlyphSet::hexadecimal();
+    let wiring = ['c', 'f', 'g', 'a', 'b', 'd', 'e'];
+    let inputs = scramble(&hex, &wiring, "0123456789AbCdEF");
+    let line = format!("{} | {}", inputs, scramble(&hex, &wiring, "C0FFEE"));
+    let mapping = Mapping::from(line.as_str());
+    let solved = mapping.solve(&hex).unwrap();
+    assert_eq!(wiring.to_vec(), solved);
+    assert_eq!(Report::Solved(solved.clone()), mapping.report(&hex));
+    assert_eq!(Some("C0FFEE".into()), mapping.decode(&hex, &solved));
+    assert_eq!(None, mapping.output(&hex, &solved));
+    // the wiring fits the inputs, but a lone top segment isn't a glyph
+    let line = format!("{} | {} c", inputs, scramble(&hex, &wiring, "C0FFEE"));
+    let mapping = Mapping::from(line.as_str());
+    assert_eq!(Some(wiring.to_vec()), mapping.solve(&hex));
+    assert_eq!(
+        Report::Inconsistent {
+            culprits: vec![(Observation::Output(6), "c".into())],
+        },
+        mapping.report(&hex)
+    );
+}
+
+#[test]
+fn test_custom_glyph_set() {
+    let alpha = GlyphSet::parse(ALPHA14).unwrap();
+    assert_eq!(14, alpha.segments);
+    let wiring = [
+        'k', 'b', 'n', 'a', 'h', 'm', 'c', 'l', 'd', 'g', 'i', 'e', 'f', 'j',
+    ];
+    let everything: String = alpha.glyphs.iter().map(|(g, _)| *g).collect();
+    let line = format!(
+        "{} | {}",
+        scramble(&alpha, &wiring, &everything),
+        scramble(&alpha, &wiring, "HELLOWORLD")
+    );
+    let mapping = Mapping::from(line.as_str());
+    let solved = mapping.solve(&alpha).unwrap();
+    assert_eq!(Some("HELLOWORLD".into()), mapping.decode(&alpha, &solved));
+    // no letter in the table tells the upper and lower left segments apart,
+    // or the upper and lower verticals, but they read the same either way
+    match mapping.report(&alpha) {
+        Report::Ambiguous {
+            candidates,
+            differing,
+        } => {
+            assert!(candidates.contains(&wiring.to_vec()));
+            assert_eq!(4, candidates.len());
+            assert!(differing.is_empty());
+        }
+        report => panic!("expected an ambiguous report, got {:?}", report),
+    }
+    // a wire the display doesn't have
+    let decimal = GlyphSet::decimal();
+    let mapping = Mapping::from("az | az");
+    assert_eq!(None, mapping.solve(&decimal));
+    assert_eq!(
+        Report::Inconsistent {
+            culprits: Vec::new()
+        },
+        mapping.report(&decimal)
+    );
+}
+
+#[test]
+fn test_glyph_table_errors() {
+    assert!(GlyphSet::parse("").is_err());
+    assert_eq!(
+        Err("line 2: expected 3 segments but found 2".into()),
+        GlyphSet::parse("a 101\nb 11").map(|set| set.segments)
+    );
+    assert_eq!(
+        Err("line 2: `b` looks the same as `a`".into()),
+        GlyphSet::parse("a 101\nb 101").map(|set| set.segments)
+    );
+    assert_eq!(
+        Err("line 1: unexpected segment value `x`".into()),
+        GlyphSet::parse("a 1x1").map(|set| set.segments)
+    );
+}
+
 #[test]
 fn part2() {
+    let decimal = GlyphSet::decimal();
     let mappings = INPUT.lines().map(Mapping::from).collect_vec();
-    let ans: u32 = mappings
+    let ans = mappings
         .iter()
         .map(|m| {
-            let unscrambled = m.unscramble();
-            m.output(&unscrambled)
+            m.solve(&decimal)
+                .and_then(|unscrambled| m.output(&decimal, &unscrambled))
+                .ok_or_else(|| m.report(&decimal))
         })
-        .sum();
+        .sum::<Result<u32, Report>>()
+        .unwrap();
     println!("Day 8, part 2: {}", ans);
     assert_eq!(1012272, ans);
 }
@@ -1,124 +1,192 @@
-use std::{collections::HashSet, mem::MaybeUninit};
+use aoc_21::{
+    heightmap::{Basin, Grid, Merge, Pool},
+    render::{self, Picture},
+};
 
 use itertools::Itertools;
 
 const INPUT: &str = include_str!("res/09.txt");
+const EXAMPLE: &str = include_str!("res/09ex.txt");
 
-const NEIGHBOR_DELTAS: [(isize, isize); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
-
-fn neighboring_points(i: isize, j: isize) -> [(usize, usize); 4] {
-    let mut neighbors: [MaybeUninit<(usize, usize)>; 4] =
-        unsafe { MaybeUninit::uninit().assume_init() };
-    for (n, delta) in NEIGHBOR_DELTAS.iter().enumerate() {
-        neighbors[n] = MaybeUninit::new(((i + delta.0) as usize, (j + delta.1) as usize));
-    }
-    unsafe { std::mem::transmute::<_, [(usize, usize); 4]>(neighbors) }
+#[test]
+fn part1() {
+    let grid: Grid = INPUT.into();
+    let ans = grid.total_risk();
+    println!("Day 9, part 1: {}", ans);
+    assert_eq!(491, ans);
 }
 
-#[derive(Debug)]
-struct Grid(Vec<Vec<u32>>);
+#[test]
+fn test_basins() {
+    let grid: Grid = EXAMPLE.into();
+    let basins = grid.basins();
+    assert_eq!(4, basins.basins.len());
+    assert_eq!(
+        Basin {
+            size: 3,
+            low_point: (0, 1),
+            bounds: ((0, 0), (1, 1)),
+        },
+        basins.basins[0]
+    );
+    assert_eq!(vec![14, 9, 9], basins.largest(3));
+    assert_eq!(None, basins.labels[0][2]);
+    assert_eq!(Some(0), basins.labels[1][0]);
+    let mut low_points = basins.basins.iter().map(|b| b.low_point).collect_vec();
+    low_points.sort_unstable();
+    assert_eq!(grid.low_points(), low_points);
+}
 
-impl From<&str> for Grid {
-    fn from(input: &str) -> Self {
-        let grid = input
-            .lines()
-            .map(|line| line.chars().map(|c| c.to_digit(10).unwrap()).collect_vec())
-            .collect_vec();
-        Grid(grid)
-    }
+#[test]
+fn test_large_basin() {
+    // one basin covering the whole map, which would have needed a recursive
+    // call per cell
+    let row = "1".repeat(500);
+    let input = vec![row; 500].join("\n");
+    let grid: Grid = input.as_str().into();
+    let basins = grid.basins();
+    assert_eq!(vec![250000], basins.largest(3));
+    assert_eq!(((0, 0), (499, 499)), basins.basins[0].bounds);
 }
 
-impl Grid {
-    fn get(&self, i: isize, j: isize) -> Option<u32> {
-        self.0
-            .get(i as usize)
-            .and_then(|v| v.get(j as usize).map(|x| *x).or(None))
+#[test]
+fn test_drainage() {
+    let grid: Grid = EXAMPLE.into();
+    let drainage = grid.drain();
+    let mut sinks = drainage.sinks.clone();
+    sinks.sort_unstable();
+    assert_eq!(grid.low_points(), sinks);
+    assert_eq!(50, drainage.areas.iter().sum::<usize>());
+    for (i, j) in grid.cells() {
+        let (r, c) = drainage.sinks[drainage.sink_of[i][j]];
+        assert!(grid.0[r][c] <= grid.0[i][j]);
     }
+    // the top-right basin also collects some of the ridge around it
+    let top_right = drainage.sinks.iter().position(|s| *s == (0, 9)).unwrap();
+    assert!(drainage.areas[top_right] > grid.basins().basins[1].size);
+}
 
-    fn neighbors(&self, i: isize, j: isize) -> Vec<u32> {
-        neighboring_points(i, j)
-            .iter()
-            .filter_map(|(r, c)| self.get(*r as isize, *c as isize))
-            .collect()
-    }
+#[test]
+fn test_drainage_plateaus() {
+    // the ridges of 9s are plateaus that spill into the basins beside them,
+    // so nothing but the low points is left holding water
+    let grid: Grid = INPUT.into();
+    let mut sinks = grid.drain().sinks;
+    sinks.sort_unstable();
+    assert_eq!(grid.low_points(), sinks);
+    // a plateau with no way out is one sink, and one with an exit drains
+    // through it
+    let drainage = Grid::from("11\n11").drain();
+    assert_eq!((vec![(0, 0)], vec![4]), (drainage.sinks, drainage.areas));
+    let drainage = Grid::from("221\n222").drain();
+    assert_eq!((vec![(0, 2)], vec![6]), (drainage.sinks, drainage.areas));
+}
 
-    fn risk_score(&self, i: usize, j: usize) -> u32 {
-        let (i, j) = (i as isize, j as isize);
-        let x = self.get(i, j).unwrap();
-        if self.neighbors(i, j).iter().all(|v| *v > x) {
-            x + 1
-        } else {
-            0
-        }
+#[test]
+fn test_flood() {
+    let grid: Grid = EXAMPLE.into();
+    let flood = grid.flood();
+    assert_eq!(10, flood.pools.len());
+    // with everything but the 9s under water, the pools are the basins
+    let basins = grid.basins();
+    assert_eq!(basins.basins.len(), flood.pools[8].len());
+    for pool in &flood.pools[8] {
+        let basin = basins.basins.iter().find(|b| b.low_point == pool.low_point);
+        assert_eq!(Some(pool.size), basin.map(|b| b.size));
     }
+    // and then the 9s join all of them together
+    assert_eq!(
+        vec![Pool {
+            low_point: (0, 9),
+            size: 50
+        }],
+        flood.pools[9]
+    );
+    assert_eq!(3, flood.merges.len());
+    assert!(flood.merges.iter().all(|m| m.level == 9));
+}
 
-    fn total_risk(&self) -> u32 {
-        let mut risk = 0;
-        for i in 0..self.0.len() {
-            for j in 0..self.0.get(i).unwrap().len() {
-                risk += self.risk_score(i, j);
-            }
-        }
-        risk
-    }
+#[test]
+fn test_flood_hierarchy() {
+    // two dips separated by a wall of 3s, and a third dip cut off from both
+    // by 5s
+    let grid: Grid = "0315\n1315\n5551\n".into();
+    let flood = grid.flood();
+    assert_eq!(3, flood.pools[1].len());
+    assert_eq!(2, flood.pools[3].len());
+    assert_eq!(1, flood.pools[5].len());
+    assert_eq!(2, flood.merges.len());
+    assert_eq!(
+        Merge {
+            level: 3,
+            survivor: (0, 0),
+            absorbed: (0, 2),
+            size: 5,
+        },
+        flood.merges[0]
+    );
+    assert_eq!(
+        (5, (0, 0), (2, 3)),
+        (
+            flood.merges[1].level,
+            flood.merges[1].survivor,
+            flood.merges[1].absorbed
+        )
+    );
+    assert_eq!(12, flood.pools[5][0].size);
+}
 
-    fn low_points(&self) -> Vec<(usize, usize)> {
-        let mut low_points = vec![];
-        for i in 0..self.0.len() {
-            for j in 0..self.0.get(i).unwrap().len() {
-                if self.risk_score(i, j) != 0 {
-                    low_points.push((i, j));
-                }
-            }
-        }
-        low_points
-    }
+#[test]
+fn test_flood_ragged() {
+    let grid = Grid::from("12\n34567");
+    // rows of different lengths, which basins have always coped with
+    assert_eq!(vec![7], grid.basins().largest(3));
+    let flood = grid.flood();
+    assert_eq!(
+        vec![Pool {
+            low_point: (0, 0),
+            size: 7
+        }],
+        flood.pools[9]
+    );
+}
 
-    fn fill_basin(&self, i: usize, j: usize, visited: &mut HashSet<(usize, usize)>) {
-        let unvisited = neighboring_points(i as isize, j as isize)
-            .iter()
-            .filter(|point| !visited.contains(*point))
-            .filter(|(r, c)| {
-                self.get(*r as isize, *c as isize)
-                    .filter(|v| *v < 9)
-                    .is_some()
-            })
-            .map(|p| *p)
-            .collect_vec();
-        for point in &unvisited {
-            visited.insert(point.clone());
-        }
-        for (r, c) in unvisited {
-            self.fill_basin(r, c, visited);
-        }
-    }
+#[test]
+fn test_render_ansi() {
+    let grid: Grid = EXAMPLE.into();
+    let mut out = Vec::new();
+    Picture::new(&grid).ansi(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(5, out.lines().count());
+    assert_eq!(4, out.matches("()").count());
+    assert!(out.lines().all(|line| line.ends_with("\x1b[0m")));
 }
 
 #[test]
-fn part1() {
-    let grid: Grid = INPUT.into();
-    let ans = grid.total_risk();
-    println!("Day 9, part 1: {}", ans);
-    assert_eq!(491, ans);
+fn test_render_ppm() {
+    let grid: Grid = EXAMPLE.into();
+    let mut out = Vec::new();
+    Picture::new(&grid).ppm(4, &mut out).unwrap();
+    let header = b"P6\n40 20\n255\n";
+    assert_eq!(header, &out[..header.len()]);
+    let pixels = &out[header.len()..];
+    assert_eq!(40 * 20 * 3, pixels.len());
+    let pixel = |x: usize, y: usize| {
+        let at = (y * 40 + x) * 3;
+        (pixels[at], pixels[at + 1], pixels[at + 2])
+    };
+    // (0, 2) is a ridge, and (0, 1) is a low point with a dot in the middle
+    assert_eq!(render::color(None, 9), pixel(8, 0));
+    assert_eq!(render::color(Some(0), 1), pixel(4, 0));
+    assert_eq!((255, 255, 255), pixel(5, 1));
+    // different basins get different colours
+    assert_ne!(render::color(Some(0), 5), render::color(Some(1), 5));
 }
 
 #[test]
 fn part2() {
     let grid: Grid = INPUT.into();
-    let low_points = grid.low_points();
-    let mut basins = vec![];
-    for (i, j) in low_points {
-        let mut visited = HashSet::new();
-        visited.insert((i, j));
-        grid.fill_basin(i, j, &mut visited);
-        println!("Low Point: {:?}, size: {}", (i, j), visited.len());
-        for point in &visited {
-            println!("\t{:?}", point);
-        }
-        basins.push(visited);
-    }
-    let mut sizes = basins.iter().map(|b| b.len()).collect_vec();
-    sizes.sort_unstable();
-    let ans: usize = sizes.iter().rev().take(3).product();
-    println!("Day 9, part 2: {}", ans)
+    let ans: usize = grid.basins().largest(3).iter().product();
+    println!("Day 9, part 2: {}", ans);
+    assert_eq!(1075536, ans);
 }
@@ -1,4 +1,8 @@
-use std::collections::VecDeque;
+use std::{
+    cmp::Ordering,
+    fmt,
+    io::{self, BufReader, Read},
+};
 
 use itertools::Itertools;
 
@@ -8,45 +12,576 @@ const INPUT: &str = include_str!("res/10.txt");
 enum LineValidation {
     Valid,
     Invalid(char),
+    /// a character that isn't a delimiter at all
+    Unknown(char),
     Incomplete(Vec<char>),
 }
 
-fn partner(c: char) -> char {
-    match c {
-        '(' => ')',
-        '[' => ']',
-        '{' => '}',
-        '<' => '>',
-        ')' => '(',
-        ']' => '[',
-        '}' => '{',
-        '>' => '<',
-        _ => panic!("invalid token"),
+/// What to do with characters that aren't part of any delimiter pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unknown {
+    Reject,
+    Ignore,
+}
+
+/// A set of delimiter pairs that have to nest properly. A pair whose opener
+/// and closer are the same character (quotes, say) closes if it's the
+/// innermost open chunk and opens otherwise.
+#[derive(Debug, Clone)]
+struct Language {
+    pairs: Vec<(char, char)>,
+    unknown: Unknown,
+}
+
+/// Where a line goes wrong, if anywhere. Columns count characters from 1.
+#[derive(Debug, PartialEq, Eq)]
+enum Diagnosis {
+    Valid,
+    /// a closer that doesn't match the innermost open chunk
+    Mismatched {
+        column: usize,
+        found: char,
+        expected: char,
+        opened_at: usize,
+    },
+    /// a closer with no open chunk at all
+    Unopened {
+        column: usize,
+        found: char,
+    },
+    /// a character that isn't in the language
+    Unknown {
+        column: usize,
+        found: char,
+    },
+    /// the line ended with chunks still open, innermost last
+    Incomplete {
+        unclosed: Vec<(usize, char)>,
+        completion: Vec<char>,
+    },
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnosis::Valid => write!(f, "ok"),
+            Diagnosis::Mismatched {
+                column,
+                found,
+                expected,
+                opened_at,
+            } => write!(
+                f,
+                "column {}: expected `{}` to close the chunk opened at column {}, found `{}`",
+                column, expected, opened_at, found
+            ),
+            Diagnosis::Unopened { column, found } => {
+                write!(f, "column {}: `{}` doesn't close anything", column, found)
+            }
+            Diagnosis::Unknown { column, found } => {
+                write!(f, "column {}: unexpected character `{}`", column, found)
+            }
+            Diagnosis::Incomplete { unclosed, .. } => {
+                let (column, opener) = unclosed.last().unwrap();
+                write!(
+                    f,
+                    "{} unclosed chunk(s), the innermost being `{}` at column {}",
+                    unclosed.len(),
+                    opener,
+                    column
+                )
+            }
+        }
     }
 }
 
-fn validate(input: &[char]) -> LineValidation {
-    let mut stack = VecDeque::new();
-    for token in input {
-        match token {
-            '(' | '[' | '{' | '<' => {
-                stack.push_front(*token);
-            }
-            _ => match stack.pop_front() {
-                Some(c) => {
-                    if c != partner(*token) {
-                        return LineValidation::Invalid(*token);
+impl Language {
+    fn new(pairs: &[(char, char)], unknown: Unknown) -> Self {
+        Language {
+            pairs: pairs.to_vec(),
+            unknown,
+        }
+    }
+
+    /// The four kinds of chunk from the puzzle
+    fn chunks() -> Self {
+        Language::new(
+            &[('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')],
+            Unknown::Reject,
+        )
+    }
+
+    fn closer(&self, opener: char) -> Option<char> {
+        self.pairs
+            .iter()
+            .find(|(open, _)| *open == opener)
+            .map(|(_, close)| *close)
+    }
+
+    fn is_closer(&self, c: char) -> bool {
+        self.pairs.iter().any(|(_, close)| *close == c)
+    }
+
+    /// Feeds one character through the chunk stack, returning the problem
+    /// if it can't be accepted
+    fn step(
+        &self,
+        stack: &mut Vec<(usize, char)>,
+        column: usize,
+        token: char,
+    ) -> Option<Diagnosis> {
+        let innermost = stack.last().map(|(_, opener)| *opener);
+        // symmetric delimiters close before they open
+        if innermost.and_then(|opener| self.closer(opener)) == Some(token) {
+            stack.pop();
+            return None;
+        }
+        if self.closer(token).is_some() {
+            stack.push((column, token));
+            return None;
+        }
+        if !self.is_closer(token) {
+            return match self.unknown {
+                Unknown::Ignore => None,
+                Unknown::Reject => Some(Diagnosis::Unknown {
+                    column,
+                    found: token,
+                }),
+            };
+        }
+        Some(match stack.last() {
+            Some(&(opened_at, opener)) => Diagnosis::Mismatched {
+                column,
+                found: token,
+                expected: self.closer(opener).unwrap(),
+                opened_at,
+            },
+            None => Diagnosis::Unopened {
+                column,
+                found: token,
+            },
+        })
+    }
+
+    /// What's left once the whole line has been read
+    fn finish(&self, stack: Vec<(usize, char)>) -> Diagnosis {
+        if stack.is_empty() {
+            return Diagnosis::Valid;
+        }
+        let completion = stack
+            .iter()
+            .rev()
+            .map(|(_, opener)| self.closer(*opener).unwrap())
+            .collect();
+        Diagnosis::Incomplete {
+            unclosed: stack,
+            completion,
+        }
+    }
+
+    fn check(&self, line: &[char]) -> Diagnosis {
+        let mut stack = Vec::new();
+        for (column, &token) in line.iter().enumerate().map(|(i, c)| (i + 1, c)) {
+            if let Some(problem) = self.step(&mut stack, column, token) {
+                return problem;
+            }
+        }
+        self.finish(stack)
+    }
+
+    /// The cheapest way to pair up `line[i]` with `line[k]`: how many of the
+    /// two need substituting, and the pair they end up as.
+    fn pairing(&self, open: char, close: char) -> (usize, (char, char)) {
+        self.pairs
+            .iter()
+            .map(|&(o, c)| ((o != open) as usize + (c != close) as usize, (o, c)))
+            .min_by_key(|(cost, _)| *cost)
+            .unwrap()
+    }
+
+    fn known(&self, c: char) -> bool {
+        self.closer(c).is_some() || self.is_closer(c)
+    }
+
+    /// Whether `c` can be one end of a chunk in a repair. Rejected unknown
+    /// characters can, by being substituted for a delimiter; ignored ones
+    /// just stay where they are.
+    fn pairable(&self, c: char) -> bool {
+        self.known(c) || self.unknown == Unknown::Reject
+    }
+
+    /// What it costs to leave `c` without a partner in the line: an unknown
+    /// character is free if ignored and deleted otherwise, an opener gets
+    /// its closer inserted, and a stray closer is deleted.
+    fn unmatched(&self, c: char) -> usize {
+        if !self.known(c) && self.unknown == Unknown::Ignore {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Finds the fewest insertions, deletions and substitutions that turn
+    /// `line` into a valid one. `costs[i][j]` is the cheapest repair of
+    /// `line[i..j]`, built up from shorter ranges: the first character is
+    /// either left unmatched or paired with some later character, which
+    /// splits the range in two.
+    fn repair(&self, line: &[char]) -> Repair {
+        let n = line.len();
+        let mut costs = vec![vec![0; n + 1]; n + 1];
+        for len in 1..=n {
+            for i in 0..=n - len {
+                let j = i + len;
+                let mut best = self.unmatched(line[i]) + costs[i + 1][j];
+                for k in i + 1..j {
+                    if !self.pairable(line[i]) || !self.pairable(line[k]) {
+                        continue;
                     }
+                    let (cost, _) = self.pairing(line[i], line[k]);
+                    best = best.min(cost + costs[i + 1][k] + costs[k + 1][j]);
                 }
-                // too many closing characters
-                None => return LineValidation::Invalid(*token),
-            },
+                costs[i][j] = best;
+            }
+        }
+        let mut edits = Vec::new();
+        let mut repaired = Vec::new();
+        self.rebuild(line, &costs, 0, n, &mut edits, &mut repaired);
+        let score = edits
+            .iter()
+            .map(|edit| {
+                let token = match edit {
+                    Edit::Insert { token, .. } | Edit::Delete { token, .. } => *token,
+                    Edit::Substitute { to, .. } => *to,
+                };
+                self.pairs
+                    .iter()
+                    .position(|(o, c)| *o == token || *c == token)
+                    .map_or(0, |p| p as u64 + 1)
+            })
+            .fold(0u64, |score, value| {
+                score
+                    .saturating_mul(self.pairs.len() as u64 + 1)
+                    .saturating_add(value)
+            });
+        Repair {
+            edits,
+            repaired,
+            score,
+        }
+    }
+
+    /// Walks back through the costs table, following whichever choice
+    /// produced each entry and writing out the edits in order
+    fn rebuild(
+        &self,
+        line: &[char],
+        costs: &[Vec<usize>],
+        i: usize,
+        j: usize,
+        edits: &mut Vec<Edit>,
+        repaired: &mut Vec<char>,
+    ) {
+        if i == j {
+            return;
+        }
+        let token = line[i];
+        if costs[i][j] == self.unmatched(token) + costs[i + 1][j] {
+            match self.closer(token) {
+                Some(closer) => {
+                    repaired.push(token);
+                    self.rebuild(line, costs, i + 1, j, edits, repaired);
+                    edits.push(Edit::Insert {
+                        at: j,
+                        token: closer,
+                    });
+                    repaired.push(closer);
+                }
+                None if self.unmatched(token) == 0 => {
+                    repaired.push(token);
+                    self.rebuild(line, costs, i + 1, j, edits, repaired);
+                }
+                None => {
+                    edits.push(Edit::Delete { at: i, token });
+                    self.rebuild(line, costs, i + 1, j, edits, repaired);
+                }
+            }
+            return;
+        }
+        for k in i + 1..j {
+            if !self.pairable(token) || !self.pairable(line[k]) {
+                continue;
+            }
+            let (cost, (open, close)) = self.pairing(token, line[k]);
+            if costs[i][j] != cost + costs[i + 1][k] + costs[k + 1][j] {
+                continue;
+            }
+            if open != token {
+                edits.push(Edit::Substitute {
+                    at: i,
+                    from: token,
+                    to: open,
+                });
+            }
+            repaired.push(open);
+            self.rebuild(line, costs, i + 1, k, edits, repaired);
+            if close != line[k] {
+                edits.push(Edit::Substitute {
+                    at: k,
+                    from: line[k],
+                    to: close,
+                });
+            }
+            repaired.push(close);
+            self.rebuild(line, costs, k + 1, j, edits, repaired);
+            return;
+        }
+        unreachable!("every entry in the costs table comes from some choice");
+    }
+}
+
+/// A single change to a line. Positions are indices into the original line,
+/// and an insertion goes just before whatever was at `at`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Insert { at: usize, token: char },
+    Delete { at: usize, token: char },
+    Substitute { at: usize, from: char, to: char },
+}
+
+/// A minimal set of edits that makes a line valid, in the order they appear
+/// along the line. The score works like `closing_score`, over the characters
+/// each edit inserts, removes or substitutes in.
+#[derive(Debug, PartialEq, Eq)]
+struct Repair {
+    edits: Vec<Edit>,
+    repaired: Vec<char>,
+    score: u64,
+}
+
+/// Something the streaming validator has to say about a line. There is
+/// exactly one event per line, and errors are reported as soon as the
+/// offending byte is read rather than at the end of the line.
+#[derive(Debug, PartialEq, Eq)]
+enum Event {
+    Valid {
+        line: usize,
+    },
+    Incomplete {
+        line: usize,
+        diagnosis: Diagnosis,
+    },
+    Corrupted {
+        line: usize,
+        diagnosis: Diagnosis,
+    },
+    /// the chunks on this line nest deeper than the limit allows
+    TooDeep {
+        line: usize,
+        column: usize,
+    },
+}
+
+/// Validates lines straight from a reader without holding a line in memory.
+/// Only the open chunks are kept, and never more than `max_depth` of them.
+/// After a corrupted line the rest of that line is skipped and validation
+/// picks up again on the next.
+struct Stream<R: Read> {
+    bytes: io::Bytes<BufReader<R>>,
+    language: Language,
+    max_depth: usize,
+    line: usize,
+}
+
+impl<R: Read> Stream<R> {
+    fn new(reader: R, language: Language, max_depth: usize) -> Self {
+        Stream {
+            bytes: BufReader::new(reader).bytes(),
+            language,
+            max_depth,
+            line: 0,
+        }
+    }
+
+    /// Reads one UTF-8 character, a byte at a time. Anything that isn't
+    /// valid UTF-8 is an `InvalidData` error.
+    fn next_char(&mut self) -> Option<io::Result<char>> {
+        let first = match self.bytes.next()? {
+            Ok(byte) => byte,
+            Err(e) => return Some(Err(e)),
         };
+        let width = match first.leading_ones() {
+            0 => 1,
+            n @ 2..=4 => n as usize,
+            _ => return Some(Err(Stream::<R>::not_utf8())),
+        };
+        let mut buffer = [first, 0, 0, 0];
+        for slot in &mut buffer[1..width] {
+            *slot = match self.bytes.next() {
+                Some(Ok(byte)) => byte,
+                Some(Err(e)) => return Some(Err(e)),
+                None => return Some(Err(Stream::<R>::not_utf8())),
+            };
+        }
+        Some(
+            std::str::from_utf8(&buffer[..width])
+                .ok()
+                .and_then(|c| c.chars().next())
+                .ok_or_else(Stream::<R>::not_utf8),
+        )
+    }
+
+    fn not_utf8() -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, "stream is not valid UTF-8")
+    }
+
+    /// Throws away everything up to and including the next newline
+    fn skip_line(&mut self) -> io::Result<()> {
+        for byte in &mut self.bytes {
+            if byte? == b'\n' {
+                break;
+            }
+        }
+        Ok(())
     }
-    if stack.is_empty() {
-        return LineValidation::Valid;
+}
+
+impl<R: Read> Iterator for Stream<R> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut stack = Vec::new();
+        let mut column = 0;
+        loop {
+            let token = match self.next_char() {
+                Some(Ok(token)) => token,
+                Some(Err(e)) => return Some(Err(e)),
+                // a final line without a trailing newline still counts
+                None if column == 0 => return None,
+                None => '\n',
+            };
+            if column == 0 {
+                self.line += 1;
+            }
+            let line = self.line;
+            if token == '\n' {
+                return Some(Ok(match self.language.finish(stack) {
+                    Diagnosis::Valid => Event::Valid { line },
+                    diagnosis => Event::Incomplete { line, diagnosis },
+                }));
+            }
+            column += 1;
+            if token == '\r' {
+                continue;
+            }
+            let event = match self.language.step(&mut stack, column, token) {
+                Some(diagnosis) => Event::Corrupted { line, diagnosis },
+                None if stack.len() > self.max_depth => Event::TooDeep { line, column },
+                None => continue,
+            };
+            return Some(self.skip_line().map(|_| event));
+        }
+    }
+}
+
+/// How `pretty` sets the completion and the corruption apart from the rest
+/// of the line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    /// trailing `#` comments
+    Plain,
+    /// the same comments, plus colour: green for the completion and red for
+    /// the corruption
+    Ansi,
+}
+
+impl Style {
+    fn paint(&self, text: &str, color: u8) -> String {
+        match self {
+            Style::Plain => text.into(),
+            Style::Ansi => format!("\x1b[{}m{}\x1b[0m", color, text),
+        }
+    }
+}
+
+/// Lays a line out as a tree with one chunk per line, its contents indented
+/// beneath it. Chunks with nothing inside stay on one line. A corrupted
+/// line stops at the bad character, with whatever followed it left as is,
+/// and an incomplete line has its missing closers filled in.
+fn pretty(language: &Language, line: &[char], style: Style) -> String {
+    const RED: u8 = 31;
+    const GREEN: u8 = 32;
+    let indent = |depth: usize| "  ".repeat(depth);
+    let mut out: Vec<String> = Vec::new();
+    let mut stack = Vec::new();
+    // whether the last line of output is an opener whose chunk may yet be empty
+    let mut pending = false;
+    // characters outside the language, gathered up until the next delimiter
+    let mut text = String::new();
+    let flush = |text: &mut String, out: &mut Vec<String>, depth: usize| {
+        if !text.trim().is_empty() {
+            out.push(format!("{}{}", indent(depth), text.trim()));
+        }
+        text.clear();
+    };
+    for (i, &token) in line.iter().enumerate() {
+        let depth = stack.len();
+        if let Some(problem) = language.step(&mut stack, i + 1, token) {
+            flush(&mut text, &mut out, depth);
+            out.push(format!(
+                "{}{}",
+                indent(depth),
+                style.paint(&format!("{}  # {}", token, problem), RED)
+            ));
+            let rest: String = line[i + 1..].iter().collect();
+            if !rest.is_empty() {
+                out.push(format!(
+                    "{}{}",
+                    indent(depth),
+                    style.paint(&format!("{}  # not checked", rest), RED)
+                ));
+            }
+            return out.join("\n");
+        }
+        match stack.len().cmp(&depth) {
+            Ordering::Greater => {
+                flush(&mut text, &mut out, depth);
+                out.push(format!("{}{}", indent(depth), token));
+                pending = true;
+            }
+            Ordering::Less if pending && text.trim().is_empty() => {
+                text.clear();
+                out.last_mut().unwrap().push(token);
+                pending = false;
+            }
+            Ordering::Less => {
+                flush(&mut text, &mut out, depth);
+                out.push(format!("{}{}", indent(depth - 1), token));
+                pending = false;
+            }
+            Ordering::Equal => text.push(token),
+        }
+    }
+    flush(&mut text, &mut out, stack.len());
+    while let Some((_, opener)) = stack.pop() {
+        let closer = language.closer(opener).unwrap();
+        out.push(format!(
+            "{}{}",
+            indent(stack.len()),
+            style.paint(&format!("{}  # added", closer), GREEN)
+        ));
+    }
+    out.join("\n")
+}
+
+fn validate(input: &[char]) -> LineValidation {
+    match Language::chunks().check(input) {
+        Diagnosis::Valid => LineValidation::Valid,
+        Diagnosis::Mismatched { found, .. } | Diagnosis::Unopened { found, .. } => {
+            LineValidation::Invalid(found)
+        }
+        Diagnosis::Unknown { found, .. } => LineValidation::Unknown(found),
+        Diagnosis::Incomplete { completion, .. } => LineValidation::Incomplete(completion),
     }
-    LineValidation::Incomplete(stack.iter().map(|c| partner(*c)).collect_vec())
 }
 
 fn closing_score(tokens: &[char]) -> u64 {
@@ -88,6 +623,14 @@ fn test_invalid_detection() {
     );
 }
 
+#[test]
+fn test_unknown_tokens() {
+    assert_eq!(
+        LineValidation::Unknown('x'),
+        validate(&"[(x)]".chars().collect_vec())
+    );
+}
+
 #[test]
 fn test_completion() {
     assert_eq!(
@@ -96,6 +639,325 @@ fn test_completion() {
     );
 }
 
+#[test]
+fn test_diagnostics() {
+    let chunks = Language::chunks();
+    let diagnosis = chunks.check(&"{([(<{}[<>[]}>{[]{[(<()>".chars().collect_vec());
+    assert_eq!(
+        Diagnosis::Mismatched {
+            column: 13,
+            found: '}',
+            expected: ']',
+            opened_at: 8,
+        },
+        diagnosis
+    );
+    assert_eq!(
+        "column 13: expected `]` to close the chunk opened at column 8, found `}`",
+        diagnosis.to_string()
+    );
+    assert_eq!(
+        Diagnosis::Unopened {
+            column: 3,
+            found: ')'
+        },
+        chunks.check(&"()))".chars().collect_vec())
+    );
+    assert_eq!(
+        Diagnosis::Unknown {
+            column: 2,
+            found: 'x'
+        },
+        chunks.check(&"(x)".chars().collect_vec())
+    );
+    let diagnosis = chunks.check(&"[()<".chars().collect_vec());
+    assert_eq!(
+        Diagnosis::Incomplete {
+            unclosed: vec![(1, '['), (4, '<')],
+            completion: vec!['>', ']'],
+        },
+        diagnosis
+    );
+    assert_eq!(
+        "2 unclosed chunk(s), the innermost being `<` at column 4",
+        diagnosis.to_string()
+    );
+}
+
+#[test]
+fn test_custom_language() {
+    let config = Language::new(&[('{', '}'), ('"', '"')], Unknown::Ignore);
+    assert_eq!(
+        Diagnosis::Valid,
+        config.check(&r#"{ "key": { "nested": "value" } }"#.chars().collect_vec())
+    );
+    assert_eq!(
+        Diagnosis::Mismatched {
+            column: 11,
+            found: '}',
+            expected: '"',
+            opened_at: 3,
+        },
+        config.check(&r#"{ "key: 1 }"#.chars().collect_vec())
+    );
+    assert_eq!(
+        Diagnosis::Incomplete {
+            unclosed: vec![(1, '{'), (10, '{')],
+            completion: vec!['}', '}'],
+        },
+        config.check(&r#"{ "key": { "a": 1"#.chars().collect_vec())
+    );
+    // parentheses mean nothing in this language
+    assert_eq!(
+        Diagnosis::Valid,
+        config.check(&"{ ) }".chars().collect_vec())
+    );
+    let strict = Language::new(&[('{', '}'), ('"', '"')], Unknown::Reject);
+    assert_eq!(
+        Diagnosis::Unknown {
+            column: 2,
+            found: ' '
+        },
+        strict.check(&"{ }".chars().collect_vec())
+    );
+}
+
+#[test]
+fn test_stream() {
+    let input =
+        "[({(<(())[]>[[{[]{<()<>>\n{([(<{}[<>[]}>{[]{[(<()>\n(())\n<{([{{}}[<[[[<>{}]]]>[]]";
+    let events = Stream::new(input.as_bytes(), Language::chunks(), 100)
+        .map(Result::unwrap)
+        .collect_vec();
+    assert_eq!(4, events.len());
+    assert!(matches!(events[0], Event::Incomplete { line: 1, .. }));
+    assert_eq!(
+        Event::Corrupted {
+            line: 2,
+            diagnosis: Diagnosis::Mismatched {
+                column: 13,
+                found: '}',
+                expected: ']',
+                opened_at: 8,
+            }
+        },
+        events[1]
+    );
+    assert_eq!(Event::Valid { line: 3 }, events[2]);
+    match &events[3] {
+        Event::Incomplete {
+            line: 4,
+            diagnosis: Diagnosis::Incomplete { completion, .. },
+        } => assert_eq!("])}>".chars().collect_vec(), *completion),
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn test_stream_depth_limit() {
+    let deep = "(".repeat(1_000_000) + &")".repeat(1_000_000);
+    let input = format!("{}\n()\r\n", deep);
+    let events = Stream::new(input.as_bytes(), Language::chunks(), 64)
+        .map(Result::unwrap)
+        .collect_vec();
+    assert_eq!(
+        vec![
+            Event::TooDeep {
+                line: 1,
+                column: 65
+            },
+            Event::Valid { line: 2 },
+        ],
+        events
+    );
+}
+
+#[test]
+fn test_stream_unicode() {
+    let guillemets = || Language::new(&[('«', '»')], Unknown::Reject);
+    let input = "«»\n«é»\n««»»»\n«";
+    let events = Stream::new(input.as_bytes(), guillemets(), 100)
+        .map(Result::unwrap)
+        .collect_vec();
+    assert_eq!(Event::Valid { line: 1 }, events[0]);
+    // columns count characters, just like `check`
+    for (n, line) in input.lines().enumerate().skip(1) {
+        let diagnosis = guillemets().check(&line.chars().collect_vec());
+        match &events[n] {
+            Event::Corrupted { diagnosis: d, .. } | Event::Incomplete { diagnosis: d, .. } => {
+                assert_eq!(diagnosis, *d)
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+    assert_eq!(
+        Event::Corrupted {
+            line: 2,
+            diagnosis: Diagnosis::Unknown {
+                column: 2,
+                found: 'é'
+            }
+        },
+        events[1]
+    );
+    // a truncated two-byte character
+    let mut stream = Stream::new(&b"\xc2"[..], guillemets(), 100);
+    let error = stream.next().unwrap().unwrap_err();
+    assert_eq!(io::ErrorKind::InvalidData, error.kind());
+}
+
+#[test]
+fn test_stream_matches_validate() {
+    let events = Stream::new(INPUT.as_bytes(), Language::chunks(), usize::MAX)
+        .map(Result::unwrap)
+        .collect_vec();
+    assert_eq!(INPUT.lines().count(), events.len());
+    for (line, event) in INPUT.lines().zip(events) {
+        let expected = validate(&line.chars().collect_vec());
+        match event {
+            Event::Valid { .. } => assert_eq!(LineValidation::Valid, expected),
+            Event::Corrupted {
+                diagnosis: Diagnosis::Mismatched { found, .. },
+                ..
+            } => assert_eq!(LineValidation::Invalid(found), expected),
+            Event::Incomplete {
+                diagnosis: Diagnosis::Incomplete { completion, .. },
+                ..
+            } => assert_eq!(LineValidation::Incomplete(completion), expected),
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+}
+
+#[test]
+fn test_repair() {
+    let chunks = Language::chunks();
+    assert_eq!(
+        Repair {
+            edits: vec![Edit::Substitute {
+                at: 1,
+                from: ']',
+                to: ')'
+            }],
+            repaired: "()".chars().collect_vec(),
+            score: 1,
+        },
+        chunks.repair(&"(]".chars().collect_vec())
+    );
+    assert_eq!(
+        Repair {
+            edits: vec![Edit::Delete { at: 2, token: '>' }],
+            repaired: "()".chars().collect_vec(),
+            score: 4,
+        },
+        chunks.repair(&"()>".chars().collect_vec())
+    );
+    // a single missing closer is the same completion that part 2 would give,
+    // with the same score
+    let repair = chunks.repair(&"[<>({})".chars().collect_vec());
+    assert_eq!(vec![Edit::Insert { at: 7, token: ']' }], repair.edits);
+    assert_eq!("[<>({})]".chars().collect_vec(), repair.repaired);
+    assert_eq!(closing_score(&[']']), repair.score);
+    // with more than one open chunk, closing an earlier one by substitution
+    // is cheaper than completing every one of them
+    let repair = chunks.repair(&"<{([".chars().collect_vec());
+    assert_eq!(2, repair.edits.len());
+    assert_eq!(LineValidation::Valid, validate(&repair.repaired));
+}
+
+#[test]
+fn test_repair_corrupted_lines() {
+    let chunks = Language::chunks();
+    for line in include_str!("res/10ex.txt").lines() {
+        let line = line.chars().collect_vec();
+        let repair = chunks.repair(&line);
+        assert_eq!(LineValidation::Valid, validate(&repair.repaired));
+        assert!(!repair.edits.is_empty());
+    }
+    // one bad closer in an otherwise fine line is a single substitution
+    assert_eq!(
+        vec![Edit::Substitute {
+            at: 5,
+            from: '}',
+            to: ']'
+        }],
+        chunks.repair(&"[(<>)}".chars().collect_vec()).edits
+    );
+    // unknown characters are left alone or deleted depending on the language
+    let lenient = Language::new(&[('(', ')')], Unknown::Ignore);
+    assert!(lenient
+        .repair(&"(a)".chars().collect_vec())
+        .edits
+        .is_empty());
+    let strict = Language::new(&[('(', ')')], Unknown::Reject);
+    assert_eq!(
+        vec![Edit::Delete { at: 1, token: 'a' }],
+        strict.repair(&"(a)".chars().collect_vec()).edits
+    );
+    // but a rejected character can also be turned into the delimiter that's
+    // missing, which beats deleting it and inserting or deleting another
+    let repair = chunks.repair(&"x)".chars().collect_vec());
+    assert_eq!(
+        vec![Edit::Substitute {
+            at: 0,
+            from: 'x',
+            to: '('
+        }],
+        repair.edits
+    );
+    assert_eq!("()".chars().collect_vec(), repair.repaired);
+    let repair = chunks.repair(&"(x".chars().collect_vec());
+    assert_eq!(
+        vec![Edit::Substitute {
+            at: 1,
+            from: 'x',
+            to: ')'
+        }],
+        repair.edits
+    );
+    assert_eq!("()".chars().collect_vec(), repair.repaired);
+}
+
+#[test]
+fn test_pretty() {
+    let chunks = Language::chunks();
+    let line = "[<>({}[]".chars().collect_vec();
+    assert_eq!(
+        "[\n  <>\n  (\n    {}\n    []\n  )  # added\n]  # added",
+        pretty(&chunks, &line, Style::Plain)
+    );
+    let line = "{([(<{}[<>[]}>{[]{[(<()>".chars().collect_vec();
+    let printed = pretty(&chunks, &line, Style::Plain);
+    assert_eq!(
+        vec![
+            "{",
+            "  (",
+            "    [",
+            "      (",
+            "        <",
+            "          {}",
+            "          [",
+            "            <>",
+            "            []",
+            "            }  # column 13: expected `]` to close the chunk opened at column 8, found `}`",
+            "            >{[]{[(<()>  # not checked",
+        ],
+        printed.lines().collect_vec()
+    );
+    let ansi = pretty(&chunks, &"(".chars().collect_vec(), Style::Ansi);
+    assert_eq!("(\n\x1b[32m)  # added\x1b[0m", ansi);
+}
+
+#[test]
+fn test_pretty_custom_language() {
+    let config = Language::new(&[('{', '}'), ('[', ']')], Unknown::Ignore);
+    let line = "{ name: x, tags: [a, b], inner: {} }".chars().collect_vec();
+    assert_eq!(
+        "{\n  name: x, tags:\n  [\n    a, b\n  ]\n  , inner:\n  {}\n}",
+        pretty(&config, &line, Style::Plain)
+    );
+}
+
 #[test]
 fn part1() {
     let ans: u32 = INPUT
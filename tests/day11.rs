@@ -1,3 +1,12 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    sync::mpsc,
+    time::Duration,
+};
+
+use aoc_21::animate::{self, Cell, Command, Frame, Player};
+
 const INPUT: &str = include_str!("res/11ex.txt");
 
 const NEIGHBOR_DELTAS: [(isize, isize); 8] = [
@@ -11,76 +20,308 @@ const NEIGHBOR_DELTAS: [(isize, isize); 8] = [
     (1, 1),
 ];
 
+/// Why a grid couldn't be read. Rows and columns count from 1.
+#[derive(Debug, PartialEq, Eq)]
+enum ParseError {
+    Empty,
+    NotADigit {
+        row: usize,
+        column: usize,
+        found: char,
+    },
+    Ragged {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "there are no octopodes in the input"),
+            ParseError::NotADigit { row, column, found } => write!(
+                f,
+                "row {}, column {}: expected an energy level from 0 to 9, found `{}`",
+                row, column, found
+            ),
+            ParseError::Ragged {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {}: expected {} octopodes like the first row, found {}",
+                row, expected, found
+            ),
+        }
+    }
+}
+
+/// What happened during a single step
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    flashes: u32,
+    /// all the flashes so far, including this step's
+    cumulative: u32,
+    /// the `(row, column)` of every octopus that flashed, in the order they
+    /// flashed
+    flashed: Vec<(usize, usize)>,
+}
+
 #[derive(Debug)]
-struct Grid<const N: usize> {
+struct Grid {
     flashes: u32,
-    octopodes: [[u32; N]; N],
+    octopodes: Vec<Vec<u32>>,
+    history: Vec<Step>,
 }
 
-impl<const N: usize> Grid<N> {
-    fn parse<'a>(mut lines: impl Iterator<Item = &'a str>) -> Option<Grid<N>> {
-        let mut octopodes = [[0; N]; N];
-        for row in &mut octopodes {
-            let mut chars = lines.next()?.chars();
-            for val in row.iter_mut() {
-                *val = chars.next()?.to_digit(10)?;
+impl Grid {
+    fn parse<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Grid, ParseError> {
+        let mut octopodes: Vec<Vec<u32>> = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let row = line
+                .chars()
+                .enumerate()
+                .map(|(j, c)| {
+                    c.to_digit(10).ok_or(ParseError::NotADigit {
+                        row: i + 1,
+                        column: j + 1,
+                        found: c,
+                    })
+                })
+                .collect::<Result<Vec<u32>, ParseError>>()?;
+            if let Some(first) = octopodes.first() {
+                if first.len() != row.len() {
+                    return Err(ParseError::Ragged {
+                        row: i + 1,
+                        expected: first.len(),
+                        found: row.len(),
+                    });
+                }
             }
+            octopodes.push(row);
+        }
+        if octopodes.first().is_none_or(Vec::is_empty) {
+            return Err(ParseError::Empty);
         }
-        Some(Grid {
+        Ok(Grid {
             flashes: 0,
             octopodes,
+            history: Vec::new(),
         })
     }
 
+    fn height(&self) -> usize {
+        self.octopodes.len()
+    }
+
+    fn width(&self) -> usize {
+        self.octopodes[0].len()
+    }
+
     fn get(&mut self, point: (isize, isize)) -> Option<&mut u32> {
         self.octopodes
             .get_mut(point.0 as usize)
             .and_then(|arr| arr.get_mut(point.1 as usize))
     }
 
-    fn update(&mut self) {
-        // first, increment everything by 1
-        for row in self.octopodes.iter_mut() {
-            for val in row.iter_mut() {
-                *val += 1;
+    /// Advances one step and returns how many octopodes flashed. Every
+    /// octopus that goes above 9 flashes exactly once, and its flash can push
+    /// neighbors over the edge in turn, so the flashes are worked through with
+    /// a queue until nothing new lights up.
+    fn update(&mut self) -> u32 {
+        let (height, width) = (self.height(), self.width());
+        let mut queue = VecDeque::new();
+        for i in 0..height {
+            for j in 0..width {
+                self.octopodes[i][j] += 1;
+                if self.octopodes[i][j] > 9 {
+                    queue.push_back((i, j));
+                }
             }
         }
-        // simulate the flash but don't zero anything out
-
-        for i in 0..N {
-            for j in 0..N {
-                let val = &mut self.octopodes[i][j];
-                if val <= &mut 9 {
-                    continue;
-                }
-                for delta in NEIGHBOR_DELTAS {
-                    if let Some(v) = self.get((i as isize + delta.0, j as isize + delta.1)) {
-                        *v += 1;
+        let mut flashed = vec![vec![false; width]; height];
+        let mut order = Vec::new();
+        while let Some((i, j)) = queue.pop_front() {
+            if flashed[i][j] {
+                continue;
+            }
+            flashed[i][j] = true;
+            order.push((i, j));
+            for delta in NEIGHBOR_DELTAS {
+                let point = (i as isize + delta.0, j as isize + delta.1);
+                if let Some(v) = self.get(point) {
+                    *v += 1;
+                    if *v > 9 {
+                        queue.push_back((point.0 as usize, point.1 as usize));
                     }
                 }
             }
         }
         // once all of the flashes have been triggered, NOW we can zero things
         // out
-        for row in self.octopodes.iter_mut() {
-            for val in row.iter_mut() {
-                if *val <= 9 {
-                    continue;
-                }
-                self.flashes += 1;
-                *val = 0;
+        for &(i, j) in &order {
+            self.octopodes[i][j] = 0;
+        }
+        let flashes = order.len() as u32;
+        self.flashes += flashes;
+        self.history.push(Step {
+            flashes,
+            cumulative: self.flashes,
+            flashed: order,
+        });
+        flashes
+    }
+
+    /// The first step (counting from 1) on which every octopus flashes, or
+    /// `None` if that hasn't happened by step `limit`. Some grids settle into a
+    /// cycle that never synchronizes, so this also gives up as soon as the
+    /// octopodes come back to energy levels they've had before.
+    fn synchronized(&mut self, limit: u32) -> Option<u32> {
+        if let Some(step) = self.first_synchronized() {
+            return Some(step);
+        }
+        let everyone = (self.height() * self.width()) as u32;
+        let mut seen = HashSet::new();
+        while (self.history.len() as u32) < limit && seen.insert(self.octopodes.clone()) {
+            if self.update() == everyone {
+                return Some(self.history.len() as u32);
             }
         }
+        None
+    }
+
+    /// Looks back through the steps taken so far for one where everybody
+    /// flashed
+    fn first_synchronized(&self) -> Option<u32> {
+        let everyone = (self.height() * self.width()) as u32;
+        self.history
+            .iter()
+            .position(|step| step.flashes == everyone)
+            .map(|i| i as u32 + 1)
+    }
+
+    /// Flashes per step, ready for plotting
+    fn flash_series(&self) -> Vec<u32> {
+        self.history.iter().map(|step| step.flashes).collect()
+    }
+
+    /// How many times the octopus at `(i, j)` has flashed so far
+    fn flashes_at(&self, i: usize, j: usize) -> usize {
+        self.history
+            .iter()
+            .filter(|step| step.flashed.contains(&(i, j)))
+            .count()
     }
 }
 
+/// The grid as it stands, with energy levels shaded and anything that flashed
+/// on the last step highlighted
+fn frame(grid: &Grid) -> Frame {
+    let flashed = grid
+        .history
+        .last()
+        .map_or(&[][..], |step| &step.flashed[..]);
+    let cells = grid
+        .octopodes
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(j, &energy)| {
+                    let glyph = char::from_digit(energy, 10).unwrap();
+                    if flashed.contains(&(i, j)) {
+                        Cell::highlight(glyph)
+                    } else {
+                        Cell::shade(glyph, energy, 9)
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let caption = format!(
+        "step {}: {} flashes, {} in total",
+        grid.history.len(),
+        grid.history.last().map_or(0, |step| step.flashes),
+        grid.flashes
+    );
+    Frame { cells, caption }
+}
+
+/// Frames for the first `steps` steps, starting from the grid as parsed
+fn frames(mut grid: Grid, steps: usize) -> impl FnMut(usize) -> Option<Frame> {
+    move |step| {
+        if step > steps {
+            return None;
+        }
+        if step > 0 {
+            grid.update();
+        }
+        Some(frame(&grid))
+    }
+}
+
+#[test]
+fn test_playback() {
+    let grid = Grid::parse(INPUT.lines()).unwrap();
+    let (sender, receiver) = mpsc::channel();
+    for command in [
+        Command::Step,
+        Command::Step,
+        Command::Slower,
+        Command::Step,
+        Command::Quit,
+    ] {
+        sender.send(command).unwrap();
+    }
+    let mut out = Vec::new();
+    let mut player = Player::new(frames(grid, 100), Duration::from_millis(100)).paused();
+    assert_eq!(4, player.run(&receiver, &mut out).unwrap());
+    assert_eq!(Duration::from_millis(200), player.delay());
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(4, out.matches("\x1b[H").count());
+    assert!(out.contains("step 2: 35 flashes, 35 in total"));
+    // every octopus that flashed on step 3 is drawn as a highlighted 0
+    let highlighted = Cell::highlight('0');
+    let (r, g, b) = highlighted.background;
+    let last_frame = out.rsplit("\x1b[H").next().unwrap();
+    assert_eq!(
+        45,
+        last_frame
+            .matches(&format!("\x1b[48;2;{};{};{}m0", r, g, b))
+            .count()
+    );
+}
+
+#[test]
+fn test_playback_to_the_end() {
+    let grid = Grid::parse(INPUT.lines()).unwrap();
+    let (sender, receiver) = mpsc::channel();
+    sender.send(Command::Faster).unwrap();
+    let mut out = Vec::new();
+    let mut player = Player::new(frames(grid, 5), Duration::from_millis(20));
+    assert_eq!(6, player.run(&receiver, &mut out).unwrap());
+    assert!(String::from_utf8(out).unwrap().ends_with("\x1b[?25h"));
+}
+
+/// Run with `cargo test --test day11 watch -- --ignored --nocapture` from a
+/// terminal: space pauses, `n` steps, `+`/`-` change the speed and `q` quits
+#[test]
+#[ignore]
+fn watch() {
+    let grid = Grid::parse(INPUT.lines()).unwrap();
+    animate::play(frames(grid, 200), Duration::from_millis(150)).unwrap();
+}
+
 #[test]
 fn test_updates() {
     let lines = INPUT.lines();
-    let mut grid = Grid::<10>::parse(lines).unwrap();
+    let mut grid = Grid::parse(lines).unwrap();
     for _ in 0..2 {
         grid.update();
-        for row in grid.octopodes {
+        for row in &grid.octopodes {
             for char in row {
                 print!("{}", char);
             }
@@ -90,12 +331,106 @@ fn test_updates() {
     }
 }
 
+#[test]
+fn test_cascade() {
+    let small = "11111\n19991\n19191\n19991\n11111";
+    let mut grid = Grid::parse(small.lines()).unwrap();
+    assert_eq!(9, grid.update());
+    assert_eq!(
+        vec![
+            vec![3, 4, 5, 4, 3],
+            vec![4, 0, 0, 0, 4],
+            vec![5, 0, 0, 0, 5],
+            vec![4, 0, 0, 0, 4],
+            vec![3, 4, 5, 4, 3],
+        ],
+        grid.octopodes
+    );
+    assert_eq!(0, grid.update());
+}
+
+#[test]
+fn test_rectangular() {
+    let mut grid = Grid::parse("989\n999".lines()).unwrap();
+    assert_eq!((2, 3), (grid.height(), grid.width()));
+    assert_eq!(Some(1), grid.synchronized(10));
+    assert_eq!(vec![0; 3], grid.octopodes[1]);
+}
+
+#[test]
+fn test_never_synchronized() {
+    // falls into a cycle in which the octopodes never all flash together
+    let mut grid = Grid::parse("334\n000".lines()).unwrap();
+    assert_eq!(None, grid.synchronized(10_000));
+    assert!(grid.history.len() < 100);
+    // the example takes 195 steps, so a lower limit stops short, and a higher
+    // one picks up where that left off
+    let mut grid = Grid::parse(INPUT.lines()).unwrap();
+    assert_eq!(None, grid.synchronized(100));
+    assert_eq!(100, grid.history.len());
+    assert_eq!(Some(195), grid.synchronized(1000));
+}
+
+#[test]
+fn test_parse_errors() {
+    assert_eq!(
+        ParseError::Ragged {
+            row: 3,
+            expected: 4,
+            found: 3
+        },
+        Grid::parse("1234\n5678\n901".lines()).unwrap_err()
+    );
+    let error = Grid::parse("1234\n56x8".lines()).unwrap_err();
+    assert_eq!(
+        "row 2, column 3: expected an energy level from 0 to 9, found `x`",
+        error.to_string()
+    );
+    assert_eq!(ParseError::Empty, Grid::parse("".lines()).unwrap_err());
+}
+
+#[test]
+fn test_history() {
+    let mut grid = Grid::parse(INPUT.lines()).unwrap();
+    for _ in 0..10 {
+        grid.update();
+    }
+    let series = grid.flash_series();
+    assert_eq!(vec![0, 35, 45, 16, 8, 1, 7, 24, 39, 29], series);
+    assert_eq!(
+        series.iter().sum::<u32>(),
+        grid.history.last().unwrap().cumulative
+    );
+    assert_eq!(series[1] as usize, grid.history[1].flashed.len());
+    assert_eq!(None, grid.first_synchronized());
+    let total: usize = (0..10)
+        .flat_map(|i| (0..10).map(move |j| (i, j)))
+        .map(|(i, j)| grid.flashes_at(i, j))
+        .sum();
+    assert_eq!(204, total);
+}
+
 #[test]
 fn part1() {
     let lines = INPUT.lines();
-    let mut grid = Grid::<10>::parse(lines).unwrap();
+    let mut grid = Grid::parse(lines).unwrap();
     for _ in 0..10 {
         grid.update();
     }
+    assert_eq!(204, grid.flashes);
+    for _ in 10..100 {
+        grid.update();
+    }
     println!("Day 11, part 1: {}", grid.flashes);
+    assert_eq!(1656, grid.flashes);
+}
+
+#[test]
+fn part2() {
+    let lines = INPUT.lines();
+    let mut grid = Grid::parse(lines).unwrap();
+    let ans = grid.synchronized(1000).unwrap();
+    println!("Day 11, part 2: {}", ans);
+    assert_eq!(195, ans);
+    assert_eq!(Some(195), grid.first_synchronized());
 }
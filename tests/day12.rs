@@ -1,4 +1,7 @@
-use std::collections::{hash_map::Entry, HashMap, HashSet};
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fmt,
+};
 
 const INPUT: &str = include_str!("res/12.txt");
 
@@ -26,10 +29,14 @@ impl Vertex<'_> {
     fn big(&self) -> bool {
         matches!(self, &Vertex::Big(_))
     }
-}
 
-trait Graph<'a> {
-    fn candidates(&self, current: &Vertex<'a>, visited: &[&Vertex<'a>]) -> Vec<&Vertex<'a>>;
+    fn name(&self) -> &str {
+        match self {
+            Vertex::Start => "start",
+            Vertex::Big(name) | Vertex::Small(name) => name,
+            Vertex::End => "end",
+        }
+    }
 }
 
 fn parse<'b>(lines: impl Iterator<Item = &'b str>) -> HashMap<Vertex<'b>, HashSet<Vertex<'b>>> {
@@ -57,82 +64,709 @@ fn parse<'b>(lines: impl Iterator<Item = &'b str>) -> HashMap<Vertex<'b>, HashSe
     vertices
 }
 
+/// The rules for how often each cave may be visited on one path. Big caves
+/// can be visited any number of times and small caves once, except that up
+/// to `revisits` small caves may be visited as many as `times` times each.
+/// `capacities` overrides all of that for particular caves, and `forbidden`
+/// caves can't be visited at all. `start` is only ever the first cave on a
+/// path, and a path stops as soon as it reaches `end`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct VisitPolicy {
+    revisits: usize,
+    times: usize,
+    capacities: HashMap<String, usize>,
+    forbidden: HashSet<String>,
+}
+
+impl VisitPolicy {
+    /// every small cave at most once
+    fn part1() -> Self {
+        VisitPolicy {
+            revisits: 0,
+            times: 1,
+            capacities: HashMap::new(),
+            forbidden: HashSet::new(),
+        }
+    }
+
+    /// a single small cave twice, the rest at most once
+    fn part2() -> Self {
+        VisitPolicy {
+            revisits: 1,
+            times: 2,
+            ..VisitPolicy::part1()
+        }
+    }
+
+    fn revisits(mut self, revisits: usize, times: usize) -> Self {
+        self.revisits = revisits;
+        self.times = times;
+        self
+    }
+
+    fn capacity(mut self, cave: &str, times: usize) -> Self {
+        self.capacities.insert(cave.into(), times);
+        self
+    }
+
+    fn forbid(mut self, cave: &str) -> Self {
+        self.forbidden.insert(cave.into());
+        self
+    }
+
+    /// Whether the visits to a small cave count against `revisits`
+    fn flexible(&self, vertex: &Vertex) -> bool {
+        matches!(vertex, Vertex::Small(name) if !self.capacities.contains_key(*name))
+    }
+
+    /// Whether a path may step into `vertex` after already having been there
+    /// `times` times, given how many small caves it has already revisited.
+    /// Nothing lets a path back into `start`, not even a capacity.
+    fn admits(&self, vertex: &Vertex, times: usize, revisited: usize) -> bool {
+        let name = vertex.name();
+        if *vertex == Vertex::Start || self.forbidden.contains(name) {
+            return false;
+        }
+        if let Some(&capacity) = self.capacities.get(name) {
+            return times < capacity;
+        }
+        match vertex {
+            Vertex::Small(_) => {
+                times == 0 || (times < self.times && (times > 1 || revisited < self.revisits))
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether visits to `vertex` need counting at all
+    fn limited(&self, vertex: &Vertex) -> bool {
+        let name = vertex.name();
+        !(vertex.big() || *vertex == Vertex::End)
+            || self.capacities.contains_key(name)
+            || self.forbidden.contains(name)
+    }
+}
+
+/// Something wrong with a cave system. Everything except `Unreachable`
+/// makes the system impossible to search: either there's nowhere to start or
+/// finish, or a path could wander back and forth forever.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CaveError {
+    MissingStart,
+    MissingEnd,
+    BigToBig(String, String),
+    SelfLoop(String),
+    /// more small caves than `Caves` has bits to keep track of
+    TooManySmallCaves(usize),
+    Unreachable(Vec<String>),
+}
+
+impl CaveError {
+    fn is_fatal(&self) -> bool {
+        !matches!(self, CaveError::Unreachable(_))
+    }
+}
+
+impl fmt::Display for CaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaveError::MissingStart => write!(f, "there is no start cave"),
+            CaveError::MissingEnd => write!(f, "there is no end cave"),
+            CaveError::BigToBig(a, b) => write!(
+                f,
+                "big caves {} and {} are connected, so a path could go back and forth between them forever",
+                a, b
+            ),
+            CaveError::SelfLoop(cave) => write!(f, "cave {} has a passage to itself", cave),
+            CaveError::TooManySmallCaves(n) => write!(
+                f,
+                "there are {} small caves, but at most {} can be kept track of",
+                n,
+                u64::BITS
+            ),
+            CaveError::Unreachable(caves) => {
+                write!(f, "unreachable from start: {}", caves.join(", "))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
-struct SimpleGraph<'a>(HashMap<Vertex<'a>, HashSet<Vertex<'a>>>);
+struct CaveGraph<'a> {
+    vertices: HashMap<Vertex<'a>, HashSet<Vertex<'a>>>,
+    policy: VisitPolicy,
+}
 
-impl<'a> SimpleGraph<'a> {
-    fn new(lines: impl Iterator<Item = &'a str>) -> Self {
+impl<'a> CaveGraph<'a> {
+    fn new(lines: impl Iterator<Item = &'a str>, policy: VisitPolicy) -> Self {
         let vertices = parse(lines);
-        Self(vertices)
+        CaveGraph { vertices, policy }
+    }
+
+    /// Every problem with the cave system, fatal ones first. A passage
+    /// between big caves is only a problem if the policy lets both of them be
+    /// visited any number of times.
+    fn validate(&self) -> Vec<CaveError> {
+        let mut errors = Vec::new();
+        if !self.vertices.contains_key(&Vertex::Start) {
+            errors.push(CaveError::MissingStart);
+        }
+        if !self.vertices.contains_key(&Vertex::End) {
+            errors.push(CaveError::MissingEnd);
+        }
+        let mut big_to_big = Vec::new();
+        let mut self_loops = Vec::new();
+        for (a, adjacent) in &self.vertices {
+            for b in adjacent {
+                if a == b {
+                    self_loops.push(a.name().to_string());
+                } else if a.name() < b.name()
+                    && a.big()
+                    && b.big()
+                    && !self.policy.limited(a)
+                    && !self.policy.limited(b)
+                {
+                    big_to_big.push((a.name().to_string(), b.name().to_string()));
+                }
+            }
+        }
+        big_to_big.sort();
+        self_loops.sort();
+        errors.extend(
+            big_to_big
+                .into_iter()
+                .map(|(a, b)| CaveError::BigToBig(a, b)),
+        );
+        errors.extend(self_loops.into_iter().map(CaveError::SelfLoop));
+        // without a start, everything is unreachable, which isn't news
+        if self.vertices.contains_key(&Vertex::Start) {
+            let mut reached = HashSet::from([&Vertex::Start]);
+            let mut frontier = vec![&Vertex::Start];
+            while let Some(vertex) = frontier.pop() {
+                for next in &self.vertices[vertex] {
+                    if reached.insert(next) {
+                        frontier.push(next);
+                    }
+                }
+            }
+            let mut unreachable = self
+                .vertices
+                .keys()
+                .filter(|v| !reached.contains(v))
+                .map(|v| v.name().to_string())
+                .collect::<Vec<_>>();
+            if !unreachable.is_empty() {
+                unreachable.sort();
+                errors.push(CaveError::Unreachable(unreachable));
+            }
+        }
+        errors
+    }
+
+    /// The first fatal problem, if there is one
+    fn check(&self) -> Result<(), CaveError> {
+        match self.validate().into_iter().find(CaveError::is_fatal) {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts the paths with the original recursive search, once the graph
+    /// is known not to send it round in circles
+    fn count_paths(&self) -> Result<u32, CaveError> {
+        self.check()?;
+        let start = self.vertices.get_key_value(&Vertex::Start).unwrap().0;
+        let (_, count) = Node::compute(start, None, self);
+        Ok(count)
     }
-}
 
-impl<'a> Graph<'a> for SimpleGraph<'a> {
     fn candidates(&self, current: &Vertex<'a>, visited: &[&Vertex<'a>]) -> Vec<&Vertex<'a>> {
-        self.0
+        // count the number of times each vertex was visited, including this
+        // one
+        let mut freqs: HashMap<_, usize> = HashMap::new();
+        for vertex in visited.iter().copied().chain([current]) {
+            *freqs.entry(vertex).or_default() += 1;
+        }
+        let revisited = freqs
+            .iter()
+            .filter(|(v, t)| self.policy.flexible(v) && **t > 1)
+            .count();
+        self.vertices
             .get(current)
             .unwrap()
             .iter()
-            .filter(|n| n.big() || !visited.contains(n))
+            .filter(|v| {
+                let times = freqs.get(v).copied().unwrap_or(0);
+                self.policy.admits(v, times, revisited)
+            })
             .collect()
     }
 }
 
+/// The cave system with every cave swapped for a small integer.
+/// `tallies[i]` says how visits to cave `i` are kept track of, so that paths
+/// which differ only in how they wandered through big caves share the same
+/// state.
 #[derive(Debug)]
-struct MultiVisitGraph<'a>(HashMap<Vertex<'a>, HashSet<Vertex<'a>>>);
+struct Caves {
+    names: Vec<String>,
+    neighbors: Vec<Vec<usize>>,
+    tallies: Vec<Tally>,
+    /// how many caves have a `Tally::Counter`
+    counters: usize,
+    policy: VisitPolicy,
+    start: usize,
+    end: usize,
+}
 
-impl<'a> MultiVisitGraph<'a> {
-    fn new(lines: impl Iterator<Item = &'a str>) -> Self {
-        let vertices = parse(lines);
-        Self(vertices)
-    }
+/// How visits to one cave are kept track of in `Visits`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tally {
+    /// they aren't: the policy doesn't care how often a path goes there
+    Free,
+    /// a small cave that can be visited at most twice gets a bit in
+    /// `Visits::once` and `Visits::twice`
+    Bit(u32),
+    /// a cave with a capacity, or a small cave that can be visited more than
+    /// twice, gets an entry in `Visits::counts`
+    Counter(usize),
 }
 
-impl<'a> Graph<'a> for MultiVisitGraph<'a> {
-    /// for a `MultiVisitGraph`, a single small cave can be visited twice. All
-    /// other small caves can be visited at most once.
-    fn candidates(&self, current: &Vertex<'a>, visited: &[&Vertex<'a>]) -> Vec<&Vertex<'a>> {
-        // count the number of times each vertex was visited
-        let mut freqs: HashMap<_, u32> = HashMap::new();
-        for vertex in visited {
-            match freqs.entry(vertex) {
-                Entry::Occupied(mut occupied) => {
-                    *occupied.get_mut() += 1;
+/// Where a path stands against the policy. For the puzzle's own policies
+/// there are no counters, so this is just a pair of bitmasks and a number.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Visits {
+    /// bit `i` is set once a path has been to the small cave with `Bit(i)`
+    once: u64,
+    /// bit `i` is set once a path has been back to that cave
+    twice: u64,
+    counts: Vec<usize>,
+    /// how many small caves without a capacity the path has revisited
+    revisited: usize,
+}
+
+impl Caves {
+    fn new(graph: &CaveGraph<'_>) -> Result<Self, CaveError> {
+        graph.check()?;
+        // sorted by name so that the numbering doesn't depend on hash order,
+        // and so that neighbors come out in alphabetical order
+        let mut names = graph.vertices.keys().collect::<Vec<_>>();
+        names.sort_by_key(|v| v.name());
+        let id = |v: &Vertex| names.iter().position(|n| *n == v).unwrap();
+        let policy = &graph.policy;
+        let (mut bits, mut counters) = (0, 0);
+        let tallies = names
+            .iter()
+            .map(|v| match v {
+                // paths only ever leave start
+                Vertex::Start => Tally::Free,
+                _ if policy.capacities.contains_key(v.name())
+                    || (policy.times > 2 && matches!(v, Vertex::Small(_))) =>
+                {
+                    counters += 1;
+                    Tally::Counter(counters - 1)
                 }
-                Entry::Vacant(vacant) => {
-                    vacant.insert(1);
+                Vertex::Small(_) => {
+                    bits += 1;
+                    Tally::Bit(bits - 1)
                 }
+                _ => Tally::Free,
+            })
+            .collect();
+        if bits > u64::BITS {
+            return Err(CaveError::TooManySmallCaves(bits as usize));
+        }
+        let neighbors = names
+            .iter()
+            .map(|v| {
+                let mut adjacent = graph.vertices[*v].iter().map(id).collect::<Vec<_>>();
+                adjacent.sort_unstable();
+                adjacent
+            })
+            .collect();
+        Ok(Caves {
+            names: names.iter().map(|v| v.name().to_string()).collect(),
+            neighbors,
+            tallies,
+            counters,
+            policy: graph.policy.clone(),
+            start: names.iter().position(|v| **v == Vertex::Start).unwrap(),
+            end: names.iter().position(|v| **v == Vertex::End).unwrap(),
+        })
+    }
+
+    /// The visits after stepping from a path with `visits` into `cave`, or
+    /// `None` if the policy doesn't allow it
+    fn enter(&self, cave: usize, visits: &Visits) -> Option<Visits> {
+        let tally = self.tallies[cave];
+        let times = match tally {
+            Tally::Free => 0,
+            Tally::Bit(bit) => ((visits.once >> bit & 1) + (visits.twice >> bit & 1)) as usize,
+            Tally::Counter(counter) => visits.counts[counter],
+        };
+        let vertex = Vertex::new(&self.names[cave]);
+        if !self.policy.admits(&vertex, times, visits.revisited) {
+            return None;
+        }
+        let mut visits = visits.clone();
+        match tally {
+            Tally::Free => return Some(visits),
+            Tally::Bit(bit) if times == 0 => visits.once |= 1 << bit,
+            Tally::Bit(bit) => visits.twice |= 1 << bit,
+            Tally::Counter(counter) => visits.counts[counter] += 1,
+        }
+        if times == 1 && self.policy.flexible(&vertex) {
+            visits.revisited += 1;
+        }
+        Some(visits)
+    }
+
+    /// The visits of a path that has only just set off from `start`
+    fn setting_off(&self) -> Visits {
+        Visits {
+            once: 0,
+            twice: 0,
+            counts: vec![0; self.counters],
+            revisited: 0,
+        }
+    }
+
+    /// Counts the paths from `start` to `end` that the policy allows
+    fn count_paths(&self) -> u64 {
+        let mut memo = HashMap::new();
+        self.count_from(self.start, self.setting_off(), &mut memo)
+    }
+
+    /// the number of ways to finish a path that has reached `cave` with
+    /// `visits` so far
+    fn count_from(&self, cave: usize, visits: Visits, memo: &mut HashMap<State, u64>) -> u64 {
+        if cave == self.end {
+            return 1;
+        }
+        let key = (cave, visits);
+        if let Some(&count) = memo.get(&key) {
+            return count;
+        }
+        let mut count = 0;
+        for &next in &self.neighbors[cave] {
+            if let Some(visits) = self.enter(next, &key.1) {
+                count += self.count_from(next, visits, memo);
             }
         }
-        // find the one vertex that was potentially visited twice
-        let twice = freqs
+        memo.insert(key, count);
+        count
+    }
+
+    /// Lazily lists the paths `count_paths` counts, in alphabetical order of
+    /// the caves along them
+    fn paths(&self) -> Paths<'_> {
+        Paths {
+            caves: self,
+            max_len: usize::MAX,
+            stack: vec![Step {
+                cave: self.start,
+                next: 0,
+                visits: self.setting_off(),
+            }],
+        }
+    }
+
+    fn id(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+}
+
+/// One path through the caves, as the caves along it in order
+#[derive(Debug, Clone)]
+struct Path<'c> {
+    caves: &'c Caves,
+    route: Vec<usize>,
+}
+
+impl Path<'_> {
+    /// the number of passages taken
+    fn len(&self) -> usize {
+        self.route.len() - 1
+    }
+
+    fn passes_through(&self, name: &str) -> bool {
+        self.caves
+            .id(name)
+            .is_some_and(|id| self.route.contains(&id))
+    }
+
+    /// whether any small cave appears on the path more than once
+    fn uses_revisit(&self) -> bool {
+        self.route.iter().enumerate().any(|(i, &cave)| {
+            matches!(Vertex::new(&self.caves.names[cave]), Vertex::Small(_))
+                && self.route[..i].contains(&cave)
+        })
+    }
+}
+
+impl Path<'_> {
+    /// the passages along the path, each as `(a, b)` with `a < b`
+    fn passages(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.route
+            .windows(2)
+            .map(|pair| (pair[0].min(pair[1]), pair[0].max(pair[1])))
+    }
+}
+
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = self
+            .route
             .iter()
-            .any(|(v, t)| matches!(v, Vertex::Small(_)) && *t >= 2);
-        // if we have already visited a node twice and `current` is being
-        // visited for a second time, we are on an invalid path.
-        if twice && visited.contains(&current) {
-            if let Vertex::Small(_) = current {
-                return Vec::new();
+            .map(|&cave| self.caves.names[cave].as_str())
+            .collect::<Vec<_>>();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+/// where the depth-first search is up to at one cave along the current path
+#[derive(Debug)]
+struct Step {
+    cave: usize,
+    /// the index of the next neighbor to try
+    next: usize,
+    visits: Visits,
+}
+
+/// A depth-first search that hands out each path as soon as it reaches `end`
+#[derive(Debug)]
+struct Paths<'c> {
+    caves: &'c Caves,
+    max_len: usize,
+    stack: Vec<Step>,
+}
+
+impl Paths<'_> {
+    /// Only paths of at most `len` passages. Unlike filtering afterwards,
+    /// this stops the search from going any deeper.
+    fn max_len(mut self, len: usize) -> Self {
+        self.max_len = len;
+        self
+    }
+}
+
+impl<'c> Iterator for Paths<'c> {
+    type Item = Path<'c>;
+
+    fn next(&mut self) -> Option<Path<'c>> {
+        let caves = self.caves;
+        loop {
+            let depth = self.stack.len();
+            if self.stack.last()?.cave == caves.end {
+                let path = Path {
+                    caves,
+                    route: self.stack.iter().map(|step| step.cave).collect(),
+                };
+                self.stack.pop();
+                return Some(path);
+            }
+            let top = self.stack.last_mut()?;
+            let next = match caves.neighbors[top.cave].get(top.next) {
+                Some(&next) if depth <= self.max_len => next,
+                _ => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            top.next += 1;
+            if let Some(visits) = caves.enter(next, &top.visits) {
+                self.stack.push(Step {
+                    cave: next,
+                    next: 0,
+                    visits,
+                });
             }
         }
-        self.0
-            .get(current)
-            .unwrap()
+    }
+}
+
+/// What to draw over the passages when exporting a diagram
+#[derive(Debug, Clone, Copy)]
+enum Overlay<'p> {
+    Plain,
+    /// the passages one path takes
+    Path(&'p Path<'p>),
+    /// how many of the valid paths take each passage
+    Heat,
+}
+
+/// a cave along with the visits of the path that reached it
+type State = (usize, Visits);
+
+/// a passage, how heavily to draw it and how often it's used
+type Weighted = ((usize, usize), Option<f64>, Option<u64>);
+
+impl Caves {
+    /// Every passage once, as `(a, b)` with `a < b`
+    fn edges(&self) -> Vec<(usize, usize)> {
+        self.neighbors
             .iter()
-            .filter(|v| match freqs.get(v) {
-                Some(&times_visited) => match v {
-                    Vertex::Start => false,
-                    Vertex::End => true,
-                    Vertex::Small(_) => match twice {
-                        true => times_visited < 1,
-                        false => times_visited < 2,
-                    },
-                    Vertex::Big(_) => true,
-                },
-                None => true,
+            .enumerate()
+            .flat_map(|(a, adjacent)| {
+                adjacent
+                    .iter()
+                    .filter(move |&&b| a < b)
+                    .map(move |&b| (a, b))
             })
             .collect()
     }
+
+    /// How many times each passage is taken, summed over every valid path.
+    /// Rather than listing the paths, this counts the ways of arriving at
+    /// each state, and a step from one state to the next is taken by that
+    /// many paths times the number of ways of finishing from the next, which
+    /// `count_from` already works out.
+    fn heat(&self) -> HashMap<(usize, usize), u64> {
+        let start = (self.start, self.setting_off());
+        let mut order = Vec::new();
+        self.postorder(start.clone(), &mut HashSet::new(), &mut order);
+        let mut memo = HashMap::new();
+        let mut arrivals = HashMap::from([(start, 1)]);
+        let mut heat = HashMap::new();
+        // every state comes before the states it can step to
+        for (cave, visits) in order.into_iter().rev() {
+            if cave == self.end {
+                continue;
+            }
+            let arrived = arrivals[&(cave, visits.clone())];
+            for &next in &self.neighbors[cave] {
+                if let Some(visits) = self.enter(next, &visits) {
+                    let finishes = self.count_from(next, visits.clone(), &mut memo);
+                    *heat.entry((cave.min(next), cave.max(next))).or_default() +=
+                        arrived * finishes;
+                    *arrivals.entry((next, visits)).or_default() += arrived;
+                }
+            }
+        }
+        heat
+    }
+
+    /// Adds the states reachable from `state` to `order`, each after all of
+    /// the states it can step to
+    fn postorder(&self, state: State, seen: &mut HashSet<State>, order: &mut Vec<State>) {
+        if !seen.insert(state.clone()) {
+            return;
+        }
+        if state.0 != self.end {
+            for &next in &self.neighbors[state.0] {
+                if let Some(visits) = self.enter(next, &state.1) {
+                    self.postorder((next, visits), seen, order);
+                }
+            }
+        }
+        order.push(state);
+    }
+
+    /// The passages to draw and how heavily, from 0 (not at all) up to 1,
+    /// with a label for heat maps
+    fn weights(&self, overlay: Overlay) -> Vec<Weighted> {
+        let edges = self.edges();
+        match overlay {
+            Overlay::Plain => edges.into_iter().map(|e| (e, None, None)).collect(),
+            Overlay::Path(path) => {
+                let taken = path.passages().collect::<HashSet<_>>();
+                edges
+                    .into_iter()
+                    .map(|e| (e, Some(if taken.contains(&e) { 1.0 } else { 0.0 }), None))
+                    .collect()
+            }
+            Overlay::Heat => {
+                let heat = self.heat();
+                let hottest = heat.values().copied().max().unwrap_or(1);
+                edges
+                    .into_iter()
+                    .map(|e| {
+                        let uses = heat.get(&e).copied().unwrap_or(0);
+                        (e, Some(uses as f64 / hottest as f64), Some(uses))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// A Graphviz diagram: big caves are boxes, small caves are circles, and
+    /// `start` and `end` are filled in
+    fn dot(&self, overlay: Overlay) -> String {
+        let mut out = String::from("graph caves {\n");
+        for (id, name) in self.names.iter().enumerate() {
+            let attributes = if id == self.start {
+                "shape=doublecircle, style=filled, fillcolor=palegreen"
+            } else if id == self.end {
+                "shape=doublecircle, style=filled, fillcolor=lightcoral"
+            } else if Vertex::new(name).big() {
+                "shape=box"
+            } else {
+                "shape=circle"
+            };
+            out += &format!("    \"{}\" [{}];\n", name, attributes);
+        }
+        for ((a, b), weight, label) in self.weights(overlay) {
+            let mut attributes = Vec::new();
+            match weight {
+                Some(w) if w > 0.0 => {
+                    attributes.push("color=red".to_string());
+                    attributes.push(format!("penwidth={:.1}", 1.0 + 4.0 * w));
+                }
+                Some(_) => attributes.push("color=grey, style=dashed".to_string()),
+                None => {}
+            }
+            if let Some(uses) = label {
+                attributes.push(format!("label=\"{}\"", uses));
+            }
+            out += &format!("    \"{}\" -- \"{}\"", self.names[a], self.names[b]);
+            if !attributes.is_empty() {
+                out += &format!(" [{}]", attributes.join(", "));
+            }
+            out += ";\n";
+        }
+        out + "}\n"
+    }
+
+    /// A Mermaid flowchart with the same conventions as `dot`. Caves get ids
+    /// like `c0` rather than their own names, because `end` is a keyword.
+    fn mermaid(&self, overlay: Overlay) -> String {
+        let mut out = String::from("flowchart LR\n");
+        for (id, name) in self.names.iter().enumerate() {
+            let node = if id == self.start || id == self.end {
+                format!("c{}([{}])", id, name)
+            } else if Vertex::new(name).big() {
+                format!("c{}[{}]", id, name)
+            } else {
+                format!("c{}(({}))", id, name)
+            };
+            out += &format!("    {}\n", node);
+        }
+        let mut styles = Vec::new();
+        for (n, ((a, b), weight, label)) in self.weights(overlay).into_iter().enumerate() {
+            match label {
+                Some(uses) => out += &format!("    c{} ---|{}| c{}\n", a, uses, b),
+                None => out += &format!("    c{} --- c{}\n", a, b),
+            }
+            match weight {
+                Some(w) if w > 0.0 => styles.push(format!(
+                    "    linkStyle {} stroke:red,stroke-width:{:.0}px\n",
+                    n,
+                    1.0 + 4.0 * w
+                )),
+                Some(_) => styles.push(format!(
+                    "    linkStyle {} stroke:grey,stroke-dasharray:4\n",
+                    n
+                )),
+                None => {}
+            }
+        }
+        out += "    classDef terminal fill:#9f9,stroke:#333,stroke-width:2px\n";
+        out += &format!("    class c{},c{} terminal\n", self.start, self.end);
+        for style in styles {
+            out += &style;
+        }
+        out
+    }
 }
 
 // the vertex container and the underlying &str have different lifetimes, but
@@ -147,7 +781,7 @@ impl<'v> Node<'v> {
     fn compute(
         vertex: &'v Vertex<'v>,
         parent: Option<&Node<'v>>,
-        graph: &'v impl Graph<'v>,
+        graph: &'v CaveGraph<'v>,
     ) -> (Node<'v>, u32) {
         // the "path" for this node is the path of the parent plus the parent
         // itself. (or an empty path if no parent)
@@ -176,22 +810,336 @@ impl<'v> Node<'v> {
             let (_, child_endpoints) = Node::compute(candidate, Some(&node), graph);
             endpoints += child_endpoints;
         }
-        return (node, endpoints);
+        (node, endpoints)
+    }
+}
+
+fn caves(input: &str, policy: VisitPolicy) -> Caves {
+    Caves::new(&CaveGraph::new(input.lines(), policy)).unwrap()
+}
+
+#[test]
+fn test_memoized_examples() {
+    let examples = [
+        (include_str!("res/12ex1.txt"), 10, 36),
+        (include_str!("res/12ex2.txt"), 19, 103),
+        (include_str!("res/12ex3.txt"), 226, 3509),
+    ];
+    for (input, simple, multi) in examples {
+        assert_eq!(simple, caves(input, VisitPolicy::part1()).count_paths());
+        assert_eq!(multi, caves(input, VisitPolicy::part2()).count_paths());
+        let graph = CaveGraph::new(input.lines(), VisitPolicy::part2());
+        assert_eq!(Ok(multi as u32), graph.count_paths());
+    }
+}
+
+#[test]
+fn test_paths() {
+    let ex1 = include_str!("res/12ex1.txt");
+    let simple = caves(ex1, VisitPolicy::part1());
+    let paths = simple.paths().map(|p| p.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            "start,A,b,A,c,A,end",
+            "start,A,b,A,end",
+            "start,A,b,end",
+            "start,A,c,A,b,A,end",
+            "start,A,c,A,b,end",
+            "start,A,c,A,end",
+            "start,A,end",
+            "start,b,A,c,A,end",
+            "start,b,A,end",
+            "start,b,end",
+        ],
+        paths
+    );
+    let multi = caves(ex1, VisitPolicy::part2());
+    let paths = multi.paths().collect::<Vec<_>>();
+    assert_eq!(36, paths.len());
+    assert_eq!("start,A,b,A,b,A,c,A,end", paths[0].to_string());
+    assert_eq!(26, paths.iter().filter(|p| p.uses_revisit()).count());
+    assert!(paths.iter().all(|p| p.passes_through("start")));
+    assert!(!paths[0].passes_through("d"));
+    assert_eq!(
+        vec!["start,A,end", "start,b,end"],
+        multi
+            .paths()
+            .filter(|p| p.len() <= 2)
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        multi.paths().filter(|p| p.len() <= 4).count(),
+        multi.paths().max_len(4).count()
+    );
+    assert_eq!(
+        vec!["start,b,d,b,end"],
+        multi
+            .paths()
+            .filter(|p| p.passes_through("d"))
+            .filter(|p| p.len() <= 4)
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_paths_match_counts() {
+    for input in [
+        include_str!("res/12ex2.txt"),
+        include_str!("res/12ex3.txt"),
+        INPUT,
+    ] {
+        let caves = caves(input, VisitPolicy::part1());
+        assert_eq!(caves.count_paths(), caves.paths().count() as u64);
+    }
+    let caves = caves(include_str!("res/12ex3.txt"), VisitPolicy::part2());
+    assert_eq!(caves.count_paths(), caves.paths().count() as u64);
+}
+
+#[test]
+fn test_policies() {
+    let ex1 = include_str!("res/12ex1.txt");
+    // no way through A leaves only the direct route through b
+    let no_a = VisitPolicy::part2().forbid("A");
+    assert_eq!(vec!["start,b,d,b,end", "start,b,end"], {
+        let caves = caves(ex1, no_a);
+        caves.paths().map(|p| p.to_string()).collect::<Vec<_>>()
+    });
+    // a big cave with a capacity of one behaves like a small cave
+    let once = VisitPolicy::part1().capacity("A", 1);
+    let caves_once = caves(ex1, once);
+    assert_eq!(
+        vec![
+            "start,A,b,end",
+            "start,A,end",
+            "start,b,A,end",
+            "start,b,end"
+        ],
+        caves_once
+            .paths()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+    );
+    // a small cave with its own capacity doesn't use up the revisit
+    let b_twice = VisitPolicy::part2().capacity("b", 2);
+    let c_twice = VisitPolicy::part2().capacity("c", 2);
+    for (policy, expected) in [(b_twice, 54), (c_twice, 54)] {
+        let graph = CaveGraph::new(ex1.lines(), policy);
+        assert_eq!(Ok(expected as u32), graph.count_paths());
+        let caves = Caves::new(&graph).unwrap();
+        assert_eq!(expected, caves.count_paths());
+        assert_eq!(expected, caves.paths().count() as u64);
+    }
+    // start stays where it is, whatever its capacity
+    let policy = VisitPolicy::part1().capacity("start", 2);
+    let graph = CaveGraph::new("start-A\nA-end".lines(), policy);
+    assert_eq!(Ok(1), graph.count_paths());
+    let caves = Caves::new(&graph).unwrap();
+    assert_eq!(1, caves.count_paths());
+    assert_eq!(
+        vec!["start,A,end"],
+        caves.paths().map(|p| p.to_string()).collect::<Vec<_>>()
+    );
+    // capacities well past anything a byte could count
+    let roomy = VisitPolicy::part1().capacity("A", 300).capacity("b", 300);
+    let graph = CaveGraph::new("start-A\nA-b\nA-end".lines(), roomy.clone());
+    assert_eq!(Ok(300), graph.count_paths());
+    assert_eq!(300, Caves::new(&graph).unwrap().count_paths());
+}
+
+#[test]
+fn test_policies_beyond_the_puzzle() {
+    let ex2 = include_str!("res/12ex2.txt");
+    let policies = [
+        VisitPolicy::part1(),
+        VisitPolicy::part2(),
+        VisitPolicy::part1().revisits(2, 2),
+        VisitPolicy::part1().revisits(1, 3),
+        VisitPolicy::part1().revisits(2, 3),
+    ];
+    let counts = policies
+        .iter()
+        .map(|policy| {
+            let graph = CaveGraph::new(ex2.lines(), policy.clone());
+            let caves = Caves::new(&graph).unwrap();
+            let count = caves.count_paths();
+            // the memoized count, the lazy paths and the original recursion
+            // all have to agree
+            assert_eq!(count, caves.paths().count() as u64);
+            assert_eq!(Ok(count as u32), graph.count_paths());
+            count
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(19, counts[0]);
+    assert_eq!(103, counts[1]);
+    assert!(counts[2] > counts[1]);
+    assert!(counts[3] > counts[1]);
+    assert!(counts[4] > counts[2].max(counts[3]));
+}
+
+#[test]
+fn test_validation() {
+    fn graph(input: &str) -> CaveGraph<'_> {
+        CaveGraph::new(input.lines(), VisitPolicy::part1())
+    }
+    assert!(graph(include_str!("res/12ex3.txt")).validate().is_empty());
+    assert!(graph(INPUT).validate().is_empty());
+    // two big caves side by side would send the search round forever
+    let looping = graph("start-A\nA-B\nB-end");
+    assert_eq!(
+        vec![CaveError::BigToBig("A".into(), "B".into())],
+        looping.validate()
+    );
+    assert_eq!(
+        Err(CaveError::BigToBig("A".into(), "B".into())),
+        looping.count_paths()
+    );
+    assert!(Caves::new(&looping).is_err());
+    assert_eq!(
+        "big caves A and B are connected, so a path could go back and forth between them forever",
+        looping.check().unwrap_err().to_string()
+    );
+    // unless the policy puts a limit on one of them
+    let limited = CaveGraph::new(
+        "start-A\nA-B\nB-end".lines(),
+        VisitPolicy::part1().capacity("B", 2),
+    );
+    assert_eq!(Ok(2), limited.count_paths());
+    assert_eq!(
+        vec![CaveError::MissingStart, CaveError::MissingEnd],
+        graph("a-b").validate()
+    );
+    assert_eq!(Err(CaveError::MissingEnd), graph("start-a").count_paths());
+    assert_eq!(
+        vec![CaveError::SelfLoop("A".into())],
+        graph("start-A\nA-A\nA-end").validate()
+    );
+    // caves that can't be reached are reported, but don't stop the search
+    let islands = graph("start-a\na-end\nb-C\nd-C");
+    assert_eq!(
+        vec![CaveError::Unreachable(vec![
+            "C".into(),
+            "b".into(),
+            "d".into()
+        ])],
+        islands.validate()
+    );
+    assert_eq!(Ok(1), islands.count_paths());
+    // one small cave too many for the bitmask
+    let crowded = ('a'..='c')
+        .flat_map(|x| ('a'..='z').map(move |y| format!("{}{}", x, y)))
+        .take(65)
+        .map(|cave| format!("start-{}\n{}-end", cave, cave))
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert_eq!(
+        Some(CaveError::TooManySmallCaves(65)),
+        Caves::new(&graph(&crowded)).err()
+    );
+}
+
+#[test]
+fn test_diagrams() {
+    let tiny = caves("start-A\nA-b\nA-end", VisitPolicy::part1());
+    assert_eq!(
+        "graph caves {\n\
+         \x20   \"A\" [shape=box];\n\
+         \x20   \"b\" [shape=circle];\n\
+         \x20   \"end\" [shape=doublecircle, style=filled, fillcolor=lightcoral];\n\
+         \x20   \"start\" [shape=doublecircle, style=filled, fillcolor=palegreen];\n\
+         \x20   \"A\" -- \"b\";\n\
+         \x20   \"A\" -- \"end\";\n\
+         \x20   \"A\" -- \"start\";\n\
+         }\n",
+        tiny.dot(Overlay::Plain)
+    );
+    assert_eq!(
+        "flowchart LR\n\
+         \x20   c0[A]\n\
+         \x20   c1((b))\n\
+         \x20   c2([end])\n\
+         \x20   c3([start])\n\
+         \x20   c0 --- c1\n\
+         \x20   c0 --- c2\n\
+         \x20   c0 --- c3\n\
+         \x20   classDef terminal fill:#9f9,stroke:#333,stroke-width:2px\n\
+         \x20   class c3,c2 terminal\n",
+        tiny.mermaid(Overlay::Plain)
+    );
+    // the only path skips b, and so the passage to b is drawn faded
+    let path = tiny.paths().find(|p| p.len() == 2).unwrap();
+    assert_eq!("start,A,end", path.to_string());
+    let dot = tiny.dot(Overlay::Path(&path));
+    assert!(dot.contains("\"A\" -- \"b\" [color=grey, style=dashed];"));
+    assert!(dot.contains("\"A\" -- \"end\" [color=red, penwidth=5.0];"));
+    let mermaid = tiny.mermaid(Overlay::Path(&path));
+    assert!(mermaid.contains("linkStyle 0 stroke:grey,stroke-dasharray:4"));
+    assert!(mermaid.contains("linkStyle 1 stroke:red,stroke-width:5px"));
+}
+
+#[test]
+fn test_heat_map() {
+    let ex1 = caves(include_str!("res/12ex1.txt"), VisitPolicy::part1());
+    let heat = ex1.heat();
+    let passage = |a: &str, b: &str| {
+        let (a, b) = (ex1.id(a).unwrap(), ex1.id(b).unwrap());
+        heat.get(&(a.min(b), a.max(b))).copied().unwrap_or(0)
+    };
+    // every path leaves start exactly once and reaches end exactly once
+    assert_eq!(7, passage("start", "A"));
+    assert_eq!(3, passage("start", "b"));
+    assert_eq!(10, passage("A", "end") + passage("b", "end"));
+    // d is a dead end for paths that may only visit b once
+    assert_eq!(0, passage("b", "d"));
+    let dot = ex1.dot(Overlay::Heat);
+    assert!(dot.contains("\"A\" -- \"start\" [color=red, penwidth="));
+    assert!(dot.contains("\"b\" -- \"d\" [color=grey, style=dashed, label=\"0\"];"));
+    let mermaid = ex1.mermaid(Overlay::Heat);
+    assert!(mermaid.contains(&format!(
+        "c{} ---|7| c{}",
+        ex1.id("A").unwrap(),
+        ex1.id("start").unwrap()
+    )));
+    // the same as tallying up the passages of every path one by one
+    for input in [include_str!("res/12ex2.txt"), include_str!("res/12ex3.txt")] {
+        for policy in [VisitPolicy::part1(), VisitPolicy::part2()] {
+            let caves = caves(input, policy);
+            let mut tally = HashMap::new();
+            for path in caves.paths() {
+                for passage in path.passages() {
+                    *tally.entry(passage).or_insert(0) += 1;
+                }
+            }
+            let heat = caves.heat();
+            for passage in caves.edges() {
+                assert_eq!(tally.get(&passage), heat.get(&passage).filter(|&&n| n > 0));
+            }
+        }
     }
+    // and quick enough for the puzzle input, where every one of the paths
+    // leaves start once
+    let input = caves(INPUT, VisitPolicy::part2());
+    let heat = input.heat();
+    let leaving = input.neighbors[input.start]
+        .iter()
+        .map(|&next| heat[&(input.start.min(next), input.start.max(next))])
+        .sum::<u64>();
+    assert_eq!(143562, leaving);
 }
 
 #[test]
 fn part1() {
-    let graph = SimpleGraph::new(INPUT.lines());
-    let (_, result) = Node::compute(&Vertex::Start, None, &graph);
+    let graph = CaveGraph::new(INPUT.lines(), VisitPolicy::part1());
+    let result = graph.count_paths().unwrap();
     println!("Day 12, part 1: {}", result);
     assert_eq!(4754, result);
+    assert_eq!(4754, Caves::new(&graph).unwrap().count_paths());
 }
 
 #[test]
 fn part2() {
-    let graph = MultiVisitGraph::new(INPUT.lines());
-    let (_, result) = Node::compute(&Vertex::Start, None, &graph);
+    let result = caves(INPUT, VisitPolicy::part2()).count_paths();
     println!("Day 12, part 2: {}", result);
     assert_eq!(143562, result);
 }
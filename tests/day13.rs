@@ -1,82 +1,380 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, fmt};
+
+use aoc_21::ocr::{self, Font, OcrError};
 
 const INPUT: &str = include_str!("res/13.txt");
+const EXAMPLE: &str = include_str!("res/13ex.txt");
 
 fn parse(token: &str) -> (u32, u32) {
     let (x, y) = token.split_once(',').unwrap();
     (x.parse().unwrap(), y.parse().unwrap())
 }
 
-fn fold_over(x: u32, line: u32) -> u32 {
+/// Where `x` ends up after folding a strip `size` long at `line`. If the far
+/// side is the longer one it hangs past the start of the near side, so
+/// everything shifts along to keep the coordinates from going negative.
+fn fold_over(x: u32, line: u32, size: u32) -> u32 {
+    let far = size.saturating_sub(line + 1);
+    let overhang = far.saturating_sub(line);
     if x < line {
-        return x;
+        x + overhang
+    } else {
+        overhang + line - (x - line)
     }
-    println!("{} {}", x, line);
-    let distance_from_line = (x - line);
-    return line - distance_from_line;
 }
 
-fn fold_x(point: (u32, u32), x_line: u32) -> (u32, u32) {
-    (fold_over(point.0, x_line), point.1)
+/// One line of the instructions, e.g. `fold along x=655`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fold {
+    /// fold the right half over to the left
+    X(u32),
+    /// fold the bottom half up
+    Y(u32),
 }
 
-fn fold_y(point: (u32, u32), y_line: u32) -> (u32, u32) {
-    (point.0, fold_over(point.1, y_line))
+impl Fold {
+    fn parse(line: &str) -> Option<Fold> {
+        let (axis, at) = line.strip_prefix("fold along ")?.split_once('=')?;
+        let at = at.parse().ok()?;
+        match axis {
+            "x" => Some(Fold::X(at)),
+            "y" => Some(Fold::Y(at)),
+            _ => None,
+        }
+    }
 }
 
-// fn fold(point: (u32, u32), lines: (u32, u32)) -> (u32, u32) {
-//     (fold_over(point.0, lines.0), fold_over(point.1, lines.1))
-// }
+impl fmt::Display for Fold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fold::X(at) => write!(f, "fold along x={}", at),
+            Fold::Y(at) => write!(f, "fold along y={}", at),
+        }
+    }
+}
 
-#[test]
-fn part1() {
-    /*
-    fold along x=655
-    fold along y=447
-    fold along x=327
-    fold along y=223
-    fold along x=163
-    fold along y=111
-    fold along x=81
-    fold along y=55
-    fold along x=40
-    fold along y=27
-    fold along y=13
-    fold along y=6
-    // (655, 447)
-    // (327, 223)
-    // (163, 111)
-    // (81, 55)
-    // (40, 27)
-    // ()
-    */
-    let points: HashSet<_> = INPUT
-        .lines()
-        .map(parse)
-        .map(|p| fold_x(p, 655))
-        .map(|p| fold_y(p, 447))
-        .map(|p| fold_x(p, 327))
-        .map(|p| fold_y(p, 223))
-        .map(|p| fold_x(p, 163))
-        .map(|p| fold_y(p, 111))
-        .map(|p| fold_x(p, 81))
-        .map(|p| fold_y(p, 55))
-        .map(|p| fold_x(p, 40))
-        .map(|p| fold_y(p, 27))
-        .map(|p| fold_y(p, 13))
-        .map(|p| fold_y(p, 6))
-        .collect();
-    // let points: HashSet<_> = INPUT
-    //     .lines()
-    //     .map(parse)
-    //     .map(|p| fold_y(p, 7))
-    //     .map(|p| fold_x(p, 5))
-    //     .collect();
-    for x in 0..10 {
-        for y in 0..10 {
-            print!("{}", if points.contains(&(x, y)) { '#' } else { '.' });
+/// A sheet of transparent paper with dots on it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Paper {
+    dots: HashSet<(u32, u32)>,
+    width: u32,
+    height: u32,
+}
+
+impl Paper {
+    fn new(dots: HashSet<(u32, u32)>) -> Self {
+        let width = dots.iter().map(|&(x, _)| x + 1).max().unwrap_or(0);
+        let height = dots.iter().map(|&(_, y)| y + 1).max().unwrap_or(0);
+        Paper {
+            dots,
+            width,
+            height,
+        }
+    }
+
+    fn fold(&self, fold: Fold) -> Paper {
+        match fold {
+            Fold::X(line) => {
+                let width = self.width.max(line + 1);
+                Paper {
+                    dots: self
+                        .dots
+                        .iter()
+                        .map(|&(x, y)| (fold_over(x, line, width), y))
+                        .collect(),
+                    width: line.max(width - line - 1),
+                    height: self.height,
+                }
+            }
+            Fold::Y(line) => {
+                let height = self.height.max(line + 1);
+                Paper {
+                    dots: self
+                        .dots
+                        .iter()
+                        .map(|&(x, y)| (x, fold_over(y, line, height)))
+                        .collect(),
+                    width: self.width,
+                    height: line.max(height - line - 1),
+                }
+            }
+        }
+    }
+}
+
+impl Paper {
+    /// The letters the dots spell out
+    fn read(&self) -> Result<String, OcrError> {
+        ocr::read_dots(self.dots.iter().map(|&(x, y)| (x as usize, y as usize)))
+    }
+}
+
+impl fmt::Display for Paper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let dot = self.dots.contains(&(x, y));
+                write!(f, "{}", if dot { '#' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// The puzzle input: the dots, a blank line, then the folds
+#[derive(Debug)]
+struct Manual {
+    paper: Paper,
+    folds: Vec<Fold>,
+}
+
+impl From<&str> for Manual {
+    fn from(input: &str) -> Self {
+        let (dots, folds) = input.split_once("\n\n").unwrap();
+        Manual {
+            paper: Paper::new(dots.lines().map(parse).collect()),
+            folds: folds
+                .lines()
+                .map(|line| Fold::parse(line).unwrap())
+                .collect(),
+        }
+    }
+}
+
+impl Manual {
+    /// The paper after the first `n` folds
+    fn after(&self, n: usize) -> Paper {
+        self.folds
+            .iter()
+            .take(n)
+            .fold(self.paper.clone(), |paper, &fold| paper.fold(fold))
+    }
+
+    fn finished(&self) -> Paper {
+        self.after(self.folds.len())
+    }
+}
+
+/// Writes the manual back out in the puzzle's input format
+impl fmt::Display for Manual {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut dots = self.paper.dots.iter().collect::<Vec<_>>();
+        dots.sort_by_key(|&&(x, y)| (y, x));
+        for (x, y) in dots {
+            writeln!(f, "{},{}", x, y)?;
+        }
+        writeln!(f)?;
+        for fold in &self.folds {
+            writeln!(f, "{}", fold)?;
         }
-        println!();
+        Ok(())
     }
-    println!("Day 13, part 1: {}", points.len());
+}
+
+/// splitmix64, which is plenty random for making up puzzles and gives
+/// something usable for any seed, 0 included
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// a number in `0..n`
+    fn below(&mut self, n: u32) -> u32 {
+        (self.next() % n as u64) as u32
+    }
+}
+
+/// The opposite of folding: a manual whose `folds` folds leave `message`
+/// written in `font`. Each unfold puts the line just past the edge of the
+/// paper and sends every dot to the near side, the far side or both. The
+/// first unfold along each axis may leave a little blank margin as well;
+/// later ones can't, since a fold leaves the paper exactly as long as the
+/// near side, and any margin would make the next fold in overhang.
+/// Fails with the first character the font can't draw.
+fn generate(message: &str, font: Font, folds: usize, seed: u64) -> Result<Manual, char> {
+    let picture = font.render(message)?;
+    let mut rng = Rng(seed);
+    let mut dots = HashSet::new();
+    for (y, row) in picture.iter().enumerate() {
+        for (x, &lit) in row.iter().enumerate() {
+            if lit {
+                dots.insert((x as u32, y as u32));
+            }
+        }
+    }
+    let mut width = picture.first().map_or(0, Vec::len) as u32;
+    let mut height = picture.len() as u32;
+    let mut unfolds: Vec<Fold> = Vec::new();
+    for _ in 0..folds {
+        let fold = if rng.below(2) == 0 {
+            let first = !unfolds.iter().any(|f| matches!(f, Fold::X(_)));
+            Fold::X(width + if first { rng.below(3) } else { 0 })
+        } else {
+            let first = !unfolds.iter().any(|f| matches!(f, Fold::Y(_)));
+            Fold::Y(height + if first { rng.below(3) } else { 0 })
+        };
+        let mirror = |(x, y): (u32, u32)| match fold {
+            Fold::X(line) => (2 * line - x, y),
+            Fold::Y(line) => (x, 2 * line - y),
+        };
+        // in order, so that the same seed always sends the same dots the same
+        // way
+        let mut sorted = dots.into_iter().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        dots = sorted
+            .into_iter()
+            .flat_map(|dot| match rng.below(3) {
+                0 => vec![dot],
+                1 => vec![mirror(dot)],
+                _ => vec![dot, mirror(dot)],
+            })
+            .collect();
+        match fold {
+            Fold::X(line) => width = 2 * line + 1,
+            Fold::Y(line) => height = 2 * line + 1,
+        }
+        unfolds.push(fold);
+    }
+    unfolds.reverse();
+    Ok(Manual {
+        paper: Paper::new(dots),
+        folds: unfolds,
+    })
+}
+
+#[test]
+fn test_parse() {
+    let manual = Manual::from(EXAMPLE);
+    assert_eq!(vec![Fold::Y(7), Fold::X(5)], manual.folds);
+    assert_eq!(18, manual.paper.dots.len());
+    assert_eq!((11, 15), (manual.paper.width, manual.paper.height));
+    assert_eq!(None, Fold::parse("fold along z=3"));
+    assert_eq!(None, Fold::parse("fold along x="));
+}
+
+#[test]
+fn test_example() {
+    let manual = Manual::from(EXAMPLE);
+    assert_eq!(manual.paper, manual.after(0));
+    assert_eq!(17, manual.after(1).dots.len());
+    let finished = manual.finished();
+    assert_eq!(16, finished.dots.len());
+    assert_eq!(
+        "#####\n#...#\n#...#\n#...#\n#####\n.....\n.....\n",
+        finished.to_string()
+    );
+}
+
+#[test]
+fn test_longer_far_side() {
+    // an even split, then a fold with more paper past the line than before it
+    assert_eq!(0, fold_over(4, 2, 5));
+    assert_eq!(1, fold_over(3, 2, 5));
+    assert_eq!(3, fold_over(0, 1, 6));
+    assert_eq!(3, fold_over(2, 1, 6));
+    assert_eq!(0, fold_over(5, 1, 6));
+    let paper = Paper::new(HashSet::from([(0, 0), (9, 0)]));
+    let folded = paper.fold(Fold::X(2));
+    assert_eq!(7, folded.width);
+    assert_eq!(HashSet::from([(5, 0), (0, 0)]), folded.dots);
+    assert_eq!("#....#.\n", folded.to_string());
+}
+
+#[test]
+fn test_ocr() {
+    let small = "\
+        .##..###...##.\n\
+        #..#.#..#.#..#\n\
+        #..#.###..#...\n\
+        ####.#..#.#...\n\
+        #..#.#..#.#..#\n\
+        #..#.###...##.";
+    assert_eq!(Ok("ABC".to_string()), ocr::read_str(small));
+    let large = "\
+        ........\n\
+        #....#..######\n\
+        ##...#.......#\n\
+        ##...#.......#\n\
+        #.#..#......#.\n\
+        #.#..#.....#..\n\
+        #..#.#....#...\n\
+        #..#.#...#....\n\
+        #...##..#.....\n\
+        #...##..#.....\n\
+        #....#..######";
+    assert_eq!(Ok("NZ".to_string()), ocr::read_str(large));
+    let narrow_and_wide = "\
+        ###.#...#\n\
+        .#..#...#\n\
+        .#...#.#.\n\
+        .#....#..\n\
+        .#....#..\n\
+        ###...#..";
+    assert_eq!(Ok("IY".to_string()), ocr::read_str(narrow_and_wide));
+    assert_eq!(Err(OcrError::Blank), ocr::read_str("....\n...."));
+    assert_eq!(Err(OcrError::Height(2)), ocr::read_str("#\n#"));
+    // the example's square is too short to be a letter
+    let square = Manual::from(EXAMPLE).finished().read();
+    assert_eq!(Err(OcrError::Height(5)), square);
+    let error = ocr::read_str("#..#.####\n#..#.#..#\n####.#..#\n#..#.#..#\n#..#.#..#\n#..#.####")
+        .unwrap_err();
+    assert_eq!(
+        "letter 2 isn't in the font:\n####\n#..#\n#..#\n#..#\n#..#\n####\n",
+        error.to_string()
+    );
+}
+
+#[test]
+fn test_generate() {
+    for seed in 0..20 {
+        let manual = generate("HELLO", Font::Small, 8, seed).unwrap();
+        assert_eq!(8, manual.folds.len());
+        // the input has to survive being written out and read back in
+        let input = manual.to_string();
+        let reread = Manual::from(input.as_str());
+        assert_eq!(manual.folds, reread.folds);
+        let finished = reread.finished();
+        assert_eq!(Ok("HELLO".to_string()), finished.read());
+        // and not just readable, but exactly the rendered message
+        let picture = Font::Small.render("HELLO").unwrap();
+        let lit = picture
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| row.iter().enumerate().map(move |(x, &lit)| (x, y, lit)))
+            .filter(|&(_, _, lit)| lit)
+            .map(|(x, y, _)| (x as u32, y as u32))
+            .collect::<HashSet<_>>();
+        assert_eq!(lit, finished.dots);
+    }
+    let large = generate("ZNXK", Font::Large, 6, 7).unwrap();
+    assert_eq!(Ok("ZNXK".to_string()), large.finished().read());
+    // the same seed makes the same puzzle, and others make different ones
+    let input = |seed| generate("FJAH", Font::Small, 10, seed).unwrap().to_string();
+    assert_eq!(input(1), input(1));
+    assert_ne!(input(1), input(2));
+    assert_eq!(Err('Q'), generate("QUIZ", Font::Small, 3, 0).map(|_| ()));
+    let yikes = generate("YIKES", Font::Small, 4, 3).unwrap();
+    assert_eq!(Ok("YIKES".to_string()), yikes.finished().read());
+}
+
+#[test]
+fn part1() {
+    let result = Manual::from(INPUT).after(1).dots.len();
+    println!("Day 13, part 1: {}", result);
+    assert_eq!(785, result);
+}
+
+#[test]
+fn part2() {
+    let paper = Manual::from(INPUT).finished();
+    let result = paper.read().unwrap();
+    println!("Day 13, part 2: {}", result);
+    assert_eq!("FJAHJGAH", result);
 }
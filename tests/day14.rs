@@ -1,9 +1,78 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::{
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    num::NonZeroU64,
+};
 
 use itertools::Itertools;
 
 const INPUT: &str = include_str!("res/14.txt");
-const SEED: &str = "HBHVVNPCNFPSVKBPPCBH";
+const EXAMPLE: &str = include_str!("res/14ex.txt");
+
+/// Why the puzzle input couldn't be used. Lines count from 1.
+#[derive(Debug, PartialEq, Eq)]
+enum InputError {
+    NoTemplate,
+    NoBlankLine,
+    Malformed {
+        line: usize,
+        text: String,
+    },
+    /// pairs that can turn up in the polymer but that no rule covers
+    MissingRules(Vec<(char, char)>),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::NoTemplate => write!(f, "the input doesn't start with a template"),
+            InputError::NoBlankLine => {
+                write!(
+                    f,
+                    "expected a blank line between the template and the rules"
+                )
+            }
+            InputError::Malformed { line, text } => {
+                write!(
+                    f,
+                    "line {}: expected a rule like `CH -> B`, found `{}`",
+                    line, text
+                )
+            }
+            InputError::MissingRules(pairs) => {
+                let pairs = pairs.iter().map(|(a, b)| format!("{}{}", a, b));
+                write!(f, "no rules for {}", pairs.collect::<Vec<_>>().join(", "))
+            }
+        }
+    }
+}
+
+/// Reads the template, a blank line and then the rules, and checks that the
+/// rules cover every pair that can come up
+fn parse(input: &str) -> Result<(Pairs, Rules), InputError> {
+    let mut lines = input.lines();
+    let template = lines
+        .next()
+        .filter(|line| !line.is_empty())
+        .ok_or(InputError::NoTemplate)?;
+    if lines.next() != Some("") {
+        return Err(InputError::NoBlankLine);
+    }
+    let mut rules = HashMap::new();
+    for (n, line) in lines.enumerate() {
+        let (pair, inserted) = Rules::rule(line).ok_or_else(|| InputError::Malformed {
+            line: n + 3,
+            text: line.into(),
+        })?;
+        rules.insert(pair, inserted);
+    }
+    let (pairs, rules) = (Pairs::from(template), Rules(rules));
+    let missing = rules.missing(&pairs);
+    if !missing.is_empty() {
+        return Err(InputError::MissingRules(missing));
+    }
+    Ok((pairs, rules))
+}
 
 #[derive(Debug)]
 struct Pairs {
@@ -25,7 +94,7 @@ impl Pairs {
                         vacant.insert(1);
                     }
                 };
-                return map;
+                map
             });
         Self {
             pairs,
@@ -45,14 +114,14 @@ impl Pairs {
                         vacant.insert(count);
                     }
                 };
-                return map;
+                map
             },
         );
         // a very annoying off-by-one error to account for the last element
         if let Some(last_character) = self.seed.chars().last() {
             *occurrences.get_mut(&last_character).unwrap() += 1;
         }
-        return occurrences;
+        occurrences
     }
 
     fn max_min_diff(&self) -> u64 {
@@ -69,7 +138,7 @@ impl Pairs {
                 max = val;
             }
         }
-        return max - min;
+        max - min
     }
 }
 
@@ -77,18 +146,64 @@ impl Pairs {
 struct Rules(HashMap<(char, char), char>);
 
 impl Rules {
-    fn from<'a>(lines: impl Iterator<Item = &'a str>) -> Self {
-        let rules = lines
-            .map(|line| line.split_once(" -> ").unwrap())
-            .map(|(first, second)| {
-                let mut first = first.chars();
-                let a = first.next().unwrap();
-                let b = first.next().unwrap();
-                let c = second.chars().next().unwrap();
-                ((a, b), c)
-            })
-            .collect();
-        return Rules(rules);
+    /// a single `AB -> C` line
+    fn rule(line: &str) -> Option<((char, char), char)> {
+        let (first, second) = line.split_once(" -> ")?;
+        let (a, b) = first.chars().collect_tuple()?;
+        let (c,) = second.chars().collect_tuple()?;
+        Some(((a, b), c))
+    }
+
+    /// Every pair that can turn up, starting from `pairs`, that has no rule,
+    /// in alphabetical order. Without a rule a pair would stop growing and
+    /// silently drop out of the counts.
+    fn missing(&self, pairs: &Pairs) -> Vec<(char, char)> {
+        let mut seen = pairs.pairs.keys().copied().collect::<HashSet<_>>();
+        let mut frontier = seen.iter().copied().collect::<Vec<_>>();
+        let mut missing = BTreeSet::new();
+        while let Some(pair) = frontier.pop() {
+            match self.0.get(&pair) {
+                Some(&inserted) => {
+                    for next in [(pair.0, inserted), (inserted, pair.1)] {
+                        if seen.insert(next) {
+                            frontier.push(next);
+                        }
+                    }
+                }
+                None => {
+                    missing.insert(pair);
+                }
+            }
+        }
+        missing.into_iter().collect()
+    }
+
+    /// The polymer `template` grows into after `steps` steps, without
+    /// writing it out
+    fn polymer(&self, template: &str, steps: usize) -> Polymer<'_> {
+        // lengths[d][pair] is how many elements `pair` grows into after `d`
+        // steps, not counting its right-hand element, which belongs to the
+        // next pair along. Pairs without a rule never grow, and so aren't
+        // stored. Lengths saturate rather than overflow, which is harmless
+        // since no index can reach that far anyway.
+        let mut lengths: Vec<HashMap<(char, char), u64>> = Vec::with_capacity(steps + 1);
+        lengths.push(self.0.keys().map(|&pair| (pair, 1)).collect());
+        for depth in 1..=steps {
+            let previous = &lengths[depth - 1];
+            let length = |pair| previous.get(&pair).copied().unwrap_or(1);
+            let current = self
+                .0
+                .iter()
+                .map(|(&(a, b), &c)| ((a, b), length((a, c)).saturating_add(length((c, b)))))
+                .collect();
+            lengths.push(current);
+        }
+        Polymer {
+            rules: self,
+            template: template.chars().collect(),
+            steps,
+            lengths,
+        }
     }
 
     fn next(&self, pairs: &Pairs) -> Pairs {
@@ -113,19 +228,550 @@ impl Rules {
                         vacant.insert(count);
                     }
                 }
-                return map;
+                map
             });
-        return Pairs {
+        Pairs {
             pairs: new,
             seed: pairs.seed.clone(),
+        }
+    }
+}
+
+/// The polymer after some number of steps, which can be read at any index
+/// without building the whole thing. Each pair of the template is the root
+/// of a binary tree of insertions, and finding an index means walking down
+/// it, picking a side by how long the left subtree grows.
+#[derive(Debug)]
+struct Polymer<'r> {
+    rules: &'r Rules,
+    template: Vec<char>,
+    steps: usize,
+    lengths: Vec<HashMap<(char, char), u64>>,
+}
+
+impl<'r> Polymer<'r> {
+    fn length(&self, pair: (char, char), depth: usize) -> u64 {
+        self.lengths[depth].get(&pair).copied().unwrap_or(1)
+    }
+
+    fn len(&self) -> u64 {
+        let pairs = self.template.iter().copied().tuple_windows();
+        pairs.fold(self.template.len().min(1) as u64, |total, pair| {
+            total.saturating_add(self.length(pair, self.steps))
+        })
+    }
+
+    fn char_at(&self, index: u64) -> Option<char> {
+        self.chars_from(index).next()
+    }
+
+    /// The elements from `start` up to but not including `end`, or `None`
+    /// if that isn't a range within the polymer
+    fn substring(&self, start: u64, end: u64) -> Option<String> {
+        if start > end || end > self.len() {
+            return None;
+        }
+        Some(
+            self.chars_from(start)
+                .take((end - start) as usize)
+                .collect(),
+        )
+    }
+
+    fn chars(&self) -> Chars<'_, 'r> {
+        self.chars_from(0)
+    }
+
+    /// The elements from `start` onwards, one at a time
+    fn chars_from(&self, start: u64) -> Chars<'_, 'r> {
+        let mut stack = self
+            .template
+            .iter()
+            .copied()
+            .tuple_windows()
+            .map(|pair| (pair, self.steps))
+            .collect::<Vec<_>>();
+        stack.reverse();
+        let mut chars = Chars {
+            polymer: self,
+            stack,
+            last: self.template.last().copied(),
         };
+        chars.skip_to(start);
+        chars
+    }
+}
+
+/// A depth-first walk of the insertion trees, left to right, holding the
+/// subtrees still to visit
+#[derive(Debug)]
+struct Chars<'p, 'r> {
+    polymer: &'p Polymer<'r>,
+    stack: Vec<((char, char), usize)>,
+    /// the template's last element, which comes after all of the trees
+    last: Option<char>,
+}
+
+impl Chars<'_, '_> {
+    /// Throws away `offset` elements, skipping whole subtrees where possible
+    fn skip_to(&mut self, mut offset: u64) {
+        while offset > 0 {
+            let (pair, depth) = match self.stack.pop() {
+                Some(top) => top,
+                None => {
+                    self.last = None;
+                    return;
+                }
+            };
+            let length = self.polymer.length(pair, depth);
+            if offset >= length {
+                offset -= length;
+                continue;
+            }
+            // the index is somewhere inside, so it has to be more than a
+            // single element
+            let inserted = self.polymer.rules.0[&pair];
+            self.stack.push(((inserted, pair.1), depth - 1));
+            self.stack.push(((pair.0, inserted), depth - 1));
+        }
+    }
+}
+
+impl Iterator for Chars<'_, '_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            let (pair, depth) = match self.stack.pop() {
+                Some(top) => top,
+                None => return self.last.take(),
+            };
+            match self.polymer.rules.0.get(&pair) {
+                Some(&inserted) if depth > 0 => {
+                    self.stack.push(((inserted, pair.1), depth - 1));
+                    self.stack.push(((pair.0, inserted), depth - 1));
+                }
+                _ => return Some(pair.0),
+            }
+        }
+    }
+}
+
+/// Counts grow with the polymer, which nearly doubles every step. On the
+/// real input the biggest pair count is about 1.5e12 after 40 steps and
+/// passes `u64` at step 64; they're kept as `u128`, which lasts until step
+/// 128. Past that, rather than wrapping silently, they either stop with
+/// `Overflow` or are taken modulo some number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Arithmetic {
+    Checked,
+    Modulo(NonZeroU64),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Overflow;
+
+impl Arithmetic {
+    /// `a` as it should be stored, which is only different under a modulus
+    fn reduce(self, a: u128) -> u128 {
+        match self {
+            Arithmetic::Checked => a,
+            Arithmetic::Modulo(m) => a % m.get() as u128,
+        }
+    }
+
+    fn add(self, a: u128, b: u128) -> Result<u128, Overflow> {
+        match self {
+            Arithmetic::Checked => a.checked_add(b).ok_or(Overflow),
+            Arithmetic::Modulo(m) => Ok((a + b) % m.get() as u128),
+        }
+    }
+
+    fn mul(self, a: u128, b: u128) -> Result<u128, Overflow> {
+        match self {
+            Arithmetic::Checked => a.checked_mul(b).ok_or(Overflow),
+            // both sides are below the modulus, which fits in a u64, so the
+            // product fits in a u128
+            Arithmetic::Modulo(m) => Ok(a * b % m.get() as u128),
+        }
+    }
+}
+
+/// A square matrix of counts, for taking many steps at once
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Matrix(Vec<Vec<u128>>);
+
+impl Matrix {
+    fn identity(n: usize) -> Self {
+        Matrix(
+            (0..n)
+                .map(|i| (0..n).map(|j| (i == j) as u128).collect())
+                .collect(),
+        )
+    }
+
+    fn mul(&self, other: &Matrix, arithmetic: Arithmetic) -> Result<Matrix, Overflow> {
+        let n = self.0.len();
+        let mut product = vec![vec![0; n]; n];
+        for (row, product) in self.0.iter().zip(&mut product) {
+            for (&a, other) in row.iter().zip(&other.0) {
+                // the transition matrix is mostly zeros
+                if a == 0 {
+                    continue;
+                }
+                for (cell, &b) in product.iter_mut().zip(other) {
+                    *cell = arithmetic.add(*cell, arithmetic.mul(a, b)?)?;
+                }
+            }
+        }
+        Ok(Matrix(product))
+    }
+
+    /// by repeated squaring
+    fn pow(&self, mut exponent: u64, arithmetic: Arithmetic) -> Result<Matrix, Overflow> {
+        let mut result = Matrix::identity(self.0.len());
+        let mut square = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&square, arithmetic)?;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                square = square.mul(&square, arithmetic)?;
+            }
+        }
+        Ok(result)
+    }
+
+    fn apply(&self, vector: &[u128], arithmetic: Arithmetic) -> Result<Vec<u128>, Overflow> {
+        self.0
+            .iter()
+            .map(|row| {
+                row.iter().zip(vector).try_fold(0, |sum, (&a, &b)| {
+                    arithmetic.add(sum, arithmetic.mul(a, b)?)
+                })
+            })
+            .collect()
+    }
+}
+
+/// The rules with every pair that can come up given an id, so that a
+/// polymer's pair counts are just a vector indexed by id
+#[derive(Debug)]
+struct Dense {
+    pairs: Vec<(char, char)>,
+    /// the two pairs each pair turns into
+    children: Vec<[usize; 2]>,
+    /// the template's pair counts
+    start: Vec<u128>,
+    /// the last element of the template, which is never part of a pair's
+    /// left side
+    last: Option<char>,
+}
+
+impl Dense {
+    fn new(template: &Pairs, rules: &Rules) -> Result<Dense, InputError> {
+        let missing = rules.missing(template);
+        if !missing.is_empty() {
+            return Err(InputError::MissingRules(missing));
+        }
+        // every pair that can come up, in alphabetical order
+        let mut pairs = BTreeSet::new();
+        let mut frontier = template.pairs.keys().copied().collect::<Vec<_>>();
+        while let Some(pair) = frontier.pop() {
+            if pairs.insert(pair) {
+                let inserted = rules.0[&pair];
+                frontier.extend([(pair.0, inserted), (inserted, pair.1)]);
+            }
+        }
+        let pairs = pairs.into_iter().collect::<Vec<_>>();
+        let id = |pair: &(char, char)| pairs.binary_search(pair).unwrap();
+        let children = pairs
+            .iter()
+            .map(|pair| {
+                let inserted = rules.0[pair];
+                [id(&(pair.0, inserted)), id(&(inserted, pair.1))]
+            })
+            .collect();
+        let mut start = vec![0; pairs.len()];
+        for (pair, &count) in &template.pairs {
+            start[id(pair)] = count as u128;
+        }
+        Ok(Dense {
+            children,
+            start,
+            last: template.seed.chars().last(),
+            pairs,
+        })
+    }
+
+    fn step(&self, counts: &[u128], arithmetic: Arithmetic) -> Result<Vec<u128>, Overflow> {
+        let mut next = vec![0; counts.len()];
+        for (&count, children) in counts.iter().zip(&self.children) {
+            for &child in children {
+                next[child] = arithmetic.add(next[child], count)?;
+            }
+        }
+        Ok(next)
+    }
+
+    /// Entry `[j][i]` is how many of pair `j` one step makes out of pair `i`
+    fn transition(&self) -> Matrix {
+        let mut matrix = vec![vec![0; self.pairs.len()]; self.pairs.len()];
+        for (i, children) in self.children.iter().enumerate() {
+            for &j in children {
+                matrix[j][i] += 1;
+            }
+        }
+        Matrix(matrix)
+    }
+
+    /// The pair counts after `steps` steps. Stepping costs about `steps`
+    /// times the number of pairs, and raising the transition matrix to a
+    /// power about the cube of the number of pairs times `log2(steps)`, so
+    /// this does whichever is cheaper.
+    fn after(&self, steps: u64, arithmetic: Arithmetic) -> Result<Vec<u128>, Overflow> {
+        let n = self.pairs.len() as u64;
+        let start = self
+            .start
+            .iter()
+            .map(|&count| arithmetic.reduce(count))
+            .collect::<Vec<_>>();
+        if steps.saturating_mul(n) <= n.pow(3) * (64 - steps.leading_zeros() as u64) {
+            (0..steps).try_fold(start, |counts, _| self.step(&counts, arithmetic))
+        } else {
+            self.transition()
+                .pow(steps, arithmetic)?
+                .apply(&start, arithmetic)
+        }
+    }
+
+    /// How many of each element there are, given pair counts
+    fn elements(
+        &self,
+        counts: &[u128],
+        arithmetic: Arithmetic,
+    ) -> Result<BTreeMap<char, u128>, Overflow> {
+        let mut elements = BTreeMap::new();
+        for (&(left, _), &count) in self.pairs.iter().zip(counts) {
+            let total = elements.entry(left).or_insert(0);
+            *total = arithmetic.add(*total, count)?;
+        }
+        if let Some(last) = self.last {
+            let total = elements.entry(last).or_insert(0);
+            *total = arithmetic.add(*total, 1)?;
+        }
+        Ok(elements)
+    }
+
+    /// The puzzle's answer: the most common element's count less the least
+    /// common's. Only meaningful with exact counts.
+    fn max_min_diff(&self, steps: u64) -> Result<u128, Overflow> {
+        let elements = self.elements(
+            &self.after(steps, Arithmetic::Checked)?,
+            Arithmetic::Checked,
+        )?;
+        let max = elements.values().max().copied().unwrap_or(0);
+        let min = elements.values().min().copied().unwrap_or(0);
+        Ok(max - min)
+    }
+}
+
+#[test]
+fn test_parse() {
+    let (pairs, rules) = parse(EXAMPLE).unwrap();
+    assert_eq!("NNCB", pairs.seed);
+    assert_eq!(16, rules.0.len());
+    assert_eq!(Some(&'B'), rules.0.get(&('C', 'H')));
+    assert_eq!(Err(InputError::NoTemplate), parse("").map(|_| ()));
+    assert_eq!(
+        Err(InputError::NoBlankLine),
+        parse("NN\nNN -> C").map(|_| ())
+    );
+    assert_eq!(
+        "line 4: expected a rule like `CH -> B`, found `NC => B`",
+        parse("NC\n\nNC -> B\nNC => B").unwrap_err().to_string()
+    );
+}
+
+#[test]
+fn test_missing_rules() {
+    // NN -> C makes NC and CN, and neither has a rule
+    let error = parse("NN\n\nNN -> C").map(|_| ()).unwrap_err();
+    assert_eq!(
+        InputError::MissingRules(vec![('C', 'N'), ('N', 'C')]),
+        error
+    );
+    assert_eq!("no rules for CN, NC", error.to_string());
+    // pairs that can never come up don't need rules
+    let (pairs, rules) = parse("AA\n\nAA -> A\nBB -> A").unwrap();
+    assert!(rules.missing(&pairs).is_empty());
+}
+
+#[test]
+fn test_example() {
+    let (mut pairs, rules) = parse(EXAMPLE).unwrap();
+    for _ in 0..10 {
+        pairs = rules.next(&pairs);
+    }
+    assert_eq!(1588, pairs.max_min_diff());
+    for _ in 10..40 {
+        pairs = rules.next(&pairs);
+    }
+    assert_eq!(2188189693529, pairs.max_min_diff());
+}
+
+#[test]
+fn test_dense() {
+    let (mut pairs, rules) = parse(EXAMPLE).unwrap();
+    let dense = Dense::new(&pairs, &rules).unwrap();
+    // the dense counts agree with the hash map ones at every step
+    let mut counts = dense.start.clone();
+    for _ in 0..20 {
+        pairs = rules.next(&pairs);
+        counts = dense.step(&counts, Arithmetic::Checked).unwrap();
+        for (pair, &count) in dense.pairs.iter().zip(&counts) {
+            assert_eq!(pairs.pairs.get(pair).copied().unwrap_or(0) as u128, count);
+        }
+    }
+    let elements = dense
+        .elements(
+            &dense.after(10, Arithmetic::Checked).unwrap(),
+            Arithmetic::Checked,
+        )
+        .unwrap();
+    assert_eq!(
+        BTreeMap::from([('B', 1749), ('C', 298), ('H', 161), ('N', 865)]),
+        elements
+    );
+    assert_eq!(Ok(1588), dense.max_min_diff(10));
+    assert_eq!(Ok(2188189693529), dense.max_min_diff(40));
+}
+
+#[test]
+fn test_matrix_power() {
+    let (pairs, rules) = parse(INPUT).unwrap();
+    let dense = Dense::new(&pairs, &rules).unwrap();
+    let transition = dense.transition();
+    let modulo = |m| Arithmetic::Modulo(NonZeroU64::new(m).unwrap());
+    for arithmetic in [Arithmetic::Checked, modulo(1_000_000_007)] {
+        for steps in [0, 1, 10, 40] {
+            let stepped = (0..steps).fold(dense.start.clone(), |counts, _| {
+                dense.step(&counts, arithmetic).unwrap()
+            });
+            let powered = transition
+                .pow(steps, arithmetic)
+                .and_then(|m| m.apply(&dense.start, arithmetic));
+            assert_eq!(Ok(stepped), powered);
+        }
+    }
+    // no steps at all still reduces the template's counts
+    let tiny = modulo(2);
+    let start = dense.start.iter().map(|c| c % 2).collect_vec();
+    assert_eq!(Ok(start), dense.after(0, tiny));
+    assert!(dense.start.iter().any(|&c| c >= 2));
+    // far past what fits in any integer type, but fine modulo a prime
+    let modulo = modulo(998_244_353);
+    let stepped = (0..5000).fold(dense.start.clone(), |counts, _| {
+        dense.step(&counts, modulo).unwrap()
+    });
+    assert_eq!(Ok(stepped), dense.after(5000, modulo));
+    assert!(dense.after(1_000_000_000_000, modulo).is_ok());
+}
+
+#[test]
+fn test_overflow() {
+    let (pairs, rules) = parse(INPUT).unwrap();
+    let dense = Dense::new(&pairs, &rules).unwrap();
+    assert!(dense.after(100, Arithmetic::Checked).is_ok());
+    assert_eq!(Err(Overflow), dense.after(200, Arithmetic::Checked));
+    assert_eq!(Err(Overflow), dense.after(10_000, Arithmetic::Checked));
+    assert_eq!(Err(Overflow), dense.max_min_diff(200));
+}
+
+#[test]
+fn test_expanded_examples() {
+    let (_, rules) = parse(EXAMPLE).unwrap();
+    let expected = [
+        "NNCB",
+        "NCNBCHB",
+        "NBCCNBBBCBHCB",
+        "NBBBCNCCNBBNBNBBCHBHHBCHB",
+        "NBBNBNBBCCNBCNCCNBBNBBNBBBNBBNBBCBHCBHHNHCBBCBHCB",
+    ];
+    for (steps, expected) in expected.iter().enumerate() {
+        let polymer = rules.polymer("NNCB", steps);
+        assert_eq!(expected.len() as u64, polymer.len());
+        assert_eq!(*expected, polymer.chars().collect::<String>());
+        for (i, c) in expected.chars().enumerate() {
+            assert_eq!(Some(c), polymer.char_at(i as u64));
+        }
+        assert_eq!(None, polymer.char_at(polymer.len()));
+        assert_eq!(Some(&expected[1..3]), polymer.substring(1, 3).as_deref());
+        assert_eq!(Some(""), polymer.substring(4, 4).as_deref());
+        assert_eq!(None, polymer.substring(4, 3));
+        assert_eq!(None, polymer.substring(0, polymer.len() + 1));
+    }
+    assert_eq!(3073, rules.polymer("NNCB", 10).len());
+    // pairs without a rule just stay put
+    let (_, rules) = parse("AB\n\nAB -> A\nAA -> B\nBA -> B\nBB -> A").unwrap();
+    let partial = Rules(
+        rules
+            .0
+            .into_iter()
+            .filter(|&(pair, _)| pair != ('B', 'A'))
+            .collect(),
+    );
+    assert_eq!("AAB", partial.polymer("AB", 1).chars().collect::<String>());
+    let polymer = partial.polymer("AB", 2);
+    assert_eq!("ABAAB", polymer.chars().collect::<String>());
+    assert_eq!(Some("BA".to_string()), polymer.substring(1, 3));
+}
+
+#[test]
+fn test_random_access() {
+    let (pairs, rules) = parse(INPUT).unwrap();
+    let polymer = rules.polymer(&pairs.seed, 10);
+    let whole = polymer.chars().collect::<Vec<_>>();
+    assert_eq!(polymer.len(), whole.len() as u64);
+    for i in (0..whole.len()).step_by(97) {
+        assert_eq!(Some(whole[i]), polymer.char_at(i as u64));
+        let end = (i + 50).min(whole.len());
+        assert_eq!(
+            Some(whole[i..end].iter().collect::<String>()),
+            polymer.substring(i as u64, end as u64)
+        );
+    }
+    // the element counts agree with the pair counting
+    let dense = Dense::new(&pairs, &rules).unwrap();
+    let elements = dense
+        .elements(
+            &dense.after(10, Arithmetic::Checked).unwrap(),
+            Arithmetic::Checked,
+        )
+        .unwrap();
+    for (&element, &count) in &elements {
+        assert_eq!(
+            count as usize,
+            whole.iter().filter(|&&c| c == element).count()
+        );
+    }
+    // 40 steps is far too long to write out, but still quick to index
+    let huge = rules.polymer(&pairs.seed, 40);
+    assert_eq!(19 * (1 << 40) + 1, huge.len());
+    assert_eq!(pairs.seed.chars().last(), huge.char_at(huge.len() - 1));
+    assert_eq!(pairs.seed.chars().next(), huge.char_at(0));
+    let middle = huge.len() / 2;
+    let window = huge.substring(middle, middle + 20).unwrap();
+    assert_eq!(20, window.len());
+    for (k, c) in window.chars().enumerate() {
+        assert_eq!(Some(c), huge.char_at(middle + k as u64));
     }
 }
 
 #[test]
 fn part1() {
-    let mut pairs = Pairs::from(SEED);
-    let rules = Rules::from(INPUT.lines());
+    let (mut pairs, rules) = parse(INPUT).unwrap();
     for _ in 0..10 {
         pairs = rules.next(&pairs);
     }
@@ -136,12 +782,13 @@ fn part1() {
 
 #[test]
 fn part2() {
-    let mut pairs = Pairs::from(SEED);
-    let rules = Rules::from(INPUT.lines());
+    let (mut pairs, rules) = parse(INPUT).unwrap();
     for _ in 0..40 {
         pairs = rules.next(&pairs);
     }
     let ans = pairs.max_min_diff();
     println!("Day 14, part 2: {ans}");
     assert_eq!(4807056953866, ans);
+    let dense = Dense::new(&parse(INPUT).unwrap().0, &rules).unwrap();
+    assert_eq!(Ok(ans as u128), dense.max_min_diff(40));
 }